@@ -0,0 +1,132 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use nucleo_nvim::injector::WalkOptions;
+use nucleo_nvim::picker::{Entry, FileEntry, Picker, SortDirection};
+
+const ROOT: &str = "/bench/root";
+const EXTENSIONS: [&str; 6] = ["rs", "lua", "md", "toml", "json", "ts"];
+const QUERIES: [&str; 5] = ["s", "sr", "src", "srcmod", "srcmodrs"];
+
+/// Builds a deterministic, roughly repo-shaped relative path for the `i`th file.
+fn synthetic_path(i: usize) -> PathBuf {
+    let ext = EXTENSIONS[i % EXTENSIONS.len()];
+    PathBuf::from(format!(
+        "src/module_{}/sub_{}/file_{}.{}",
+        i % 97,
+        (i / 97) % 31,
+        i,
+        ext
+    ))
+}
+
+/// Creates a picker whose matcher already contains `count` synthetic entries.
+fn injected_picker(count: usize) -> Picker<FileEntry> {
+    let mut picker = Picker::new(ROOT.to_string(), SortDirection::Descending);
+    let injector = picker.matcher.injector();
+    for i in 0..count {
+        let path = Path::new(ROOT).join(synthetic_path(i));
+        let entry = FileEntry::from_path(&path, Some(ROOT.to_string())).unwrap();
+        injector.push(entry.clone(), |dst| dst[0] = entry.into_utf32());
+    }
+    settle(&mut picker);
+    picker
+}
+
+fn settle(picker: &mut Picker<FileEntry>) {
+    while picker.tick(10).0.running {}
+}
+
+fn create_tree(count: usize) -> tempfile::TempDir {
+    let dir = tempfile::tempdir().expect("Failed to create temp dir");
+    for i in 0..count {
+        let path = dir.path().join(synthetic_path(i));
+        fs::create_dir_all(path.parent().unwrap()).expect("Failed to create directory");
+        fs::write(path, "").expect("Failed to create file");
+    }
+    dir
+}
+
+fn bench_populate_files_sorted(c: &mut Criterion) {
+    let mut group = c.benchmark_group("populate_files_sorted");
+    group.sample_size(10);
+
+    for count in [10_000, 100_000, 1_000_000] {
+        let tree = create_tree(count);
+        let cwd = tree.path().to_string_lossy().to_string();
+        group.throughput(criterion::Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(count), &cwd, |b, cwd| {
+            b.iter_batched(
+                || Picker::<FileEntry>::new(cwd.clone(), SortDirection::Descending),
+                |mut picker| {
+                    picker
+                        .matcher
+                        .injector()
+                        .populate_files_sorted(
+                            cwd.clone(),
+                            None,
+                            WalkOptions::default(),
+                            Arc::default(),
+                        )
+                        .join()
+                        .unwrap()
+                        .unwrap();
+                    while picker.total_items() < count as u32 {
+                        picker.tick(10);
+                    }
+                    picker
+                },
+                BatchSize::PerIteration,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_incremental_query(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update_query_tick");
+    group.sample_size(10);
+
+    for count in [10_000, 100_000, 1_000_000] {
+        let mut picker = injected_picker(count);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| {
+                for query in QUERIES {
+                    picker.update_query(query.to_string());
+                    settle(&mut picker);
+                }
+                picker.update_query(String::new());
+                settle(&mut picker);
+                black_box(picker.total_matches())
+            });
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_current_matches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("current_matches");
+
+    for count in [10_000, 100_000, 1_000_000] {
+        let mut picker = injected_picker(count);
+        picker.update_query("srcmod".to_string());
+        settle(&mut picker);
+        group.bench_function(BenchmarkId::from_parameter(count), |b| {
+            b.iter(|| black_box(picker.current_matches()));
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_populate_files_sorted,
+    bench_incremental_query,
+    bench_current_matches
+);
+criterion_main!(benches);
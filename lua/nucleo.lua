@@ -6,8 +6,53 @@ M._rust = {
 	Previewer = true,
 }
 
-function M.setup(...)
-	require("nucleo.pickers").setup(...)
+---@class Nucleo.LogConfig
+---@field target? "file"|"lua"|"off"
+---@field path? string
+---@field level? "off"|"error"|"warn"|"info"|"debug"|"trace"
+---@field max_size? integer
+---@field max_files? integer
+
+---@param opts? Nucleo.LogConfig
+function M.setup_logging(opts)
+	opts = opts or {}
+	require("nucleo_rs").setup_logging(opts)
+
+	if M._log_timer then
+		M._log_timer:stop()
+		M._log_timer:close()
+		M._log_timer = nil
+	end
+
+	if opts.target == "lua" then
+		local log = require("nucleo.log")
+		M._log_timer = vim.uv.new_timer()
+		M._log_timer:start(
+			1000,
+			1000,
+			vim.schedule_wrap(function()
+				for _, record in ipairs(require("nucleo_rs").drain_logs()) do
+					log[record.level](record.message)
+				end
+			end)
+		)
+	end
+end
+
+function M.setup(opts)
+	opts = opts or {}
+	if opts.log then
+		M.setup_logging(opts.log)
+	end
+
+	-- Records are otherwise only drained by the `lua` target's timer, which would pick these up
+	if not opts.log or opts.log.target ~= "lua" then
+		for _, record in ipairs(require("nucleo_rs").drain_logs()) do
+			vim.notify("nucleo: " .. record.message, vim.log.levels.WARN)
+		end
+	end
+
+	require("nucleo.pickers").setup(opts)
 end
 
 function M.find(...)
@@ -16,7 +16,12 @@ function Entry:new(index, entry, bufnr)
 	self.bufnr = bufnr
 	self.selection_caret = " "
 
-	local value, color = require("nvim-web-devicons").get_icon(entry.path, entry.file_type, { default = true })
+	local value, color
+	if entry.kind == "directory" then
+		value, color = "", "Directory"
+	else
+		value, color = require("nvim-web-devicons").get_icon(entry.path, entry.file_type, { default = true })
+	end
 	self.icon = {
 		value = value,
 		color = color,
@@ -27,9 +32,10 @@ function Entry:render()
 	local picker_icon = Text(self.selection_caret, "Normal")
 	local icon = Text(self.icon.value, self.icon.color)
 	local path = Text(self.entry.match_value)
-	local line = Line({ picker_icon, icon, path })
+	local root = Text(self.entry.root and (self.entry.root .. ": ") or "", "Comment")
+	local line = Line({ picker_icon, icon, root, path })
 
-	local leading_length = picker_icon:length() + icon:length()
+	local leading_length = picker_icon:length() + icon:length() + root:length()
 
 	line:render(self.bufnr, -1, self.index)
 	vim.iter(self.entry.indices):each(function(range)
@@ -25,7 +25,22 @@ local M = {}
 
 ---@class PickerEntry
 ---@field path string
+---@field raw_path? string Original bytes of `path` when it isn't valid UTF-8
 ---@field file_type string
+---@field kind "file"|"directory"
+---@field root? string Label of the root the entry came from when searching several roots
+---@field metadata? PickerEntryMetadata
+---@field indices [integer, integer][]
+---@field score? integer Fuzzy score that `min_score` is compared against, set on `current_matches` and `matches`
+---@field boost? integer Proximity bonus the match was ranked with on top of `score`
+
+---@class PickerEntryMetadata
+---@field size integer
+---@field mtime? integer
+---@field permissions? integer
+---@field readonly boolean
+---@field is_executable boolean
+---@field symlink_target? string
 
 ---@class PickerStatus
 ---@field running boolean
@@ -34,7 +49,8 @@ local M = {}
 ---@class Picker
 ---@field update_query fun(self: Picker, query: string)
 ---@field update_cwd fun(self: Picker, cwd: string)
----@field update_config fun(self: Picker, config: Nucleo.FilePicker.Config)
+---@field roots fun(self: Picker): { path: string, label: string }[]
+---@field update_config fun(self: Picker, config: Nucleo.FilePicker.Config): boolean Whether the picker re-indexed
 ---@field update_window fun(self: Picker, height: integer)
 ---@field populate_files fun(self: Picker)
 ---@field restart fun(self: Picker)
@@ -54,6 +70,9 @@ local M = {}
 ---@field set_cursor fun(self: Picker, pos: integer)
 ---@field window_height fun(self: Picker): integer
 ---@field sort_direction fun(self: Picker): "descending"|"ascending"
+---@field record_open fun(self: Picker, path: string)
+---@field matches fun(self: Picker, offset?: integer, limit?: integer): PickerEntry[] Reads any range of matches without touching the cursor or window
+---@field quickfix_items fun(self: Picker, source?: "matches"|"selections", offset?: integer, limit?: integer): { filename: string, lnum: integer, col: integer, text: string }[]
 
 ---@type Picker|nil
 M.picker = nil
@@ -78,6 +97,7 @@ end
 ---@param val string
 M.process_input = debounce(function(val)
 	M.picker:update_query(val)
+	M.previewer:set_query(val)
 	-- M.picker:force_rerender()
 	log.info("Updated input: " .. val)
 
@@ -89,6 +109,18 @@ end, 50)
 ---@param opts? Nucleo.FilePicker.Config
 M.initialize = function(opts)
 	opts = opts or { cwd = vim.uv.cwd() }
+	opts.reference_path = opts.reference_path or api.nvim_buf_get_name(0)
+	opts.open_buffers = opts.open_buffers
+		or vim.iter(api.nvim_list_bufs())
+			:filter(function(bufnr)
+				return vim.bo[bufnr].buflisted
+			end)
+			:map(api.nvim_buf_get_name)
+			:filter(function(name)
+				return name ~= ""
+			end)
+			:totable()
+	opts.oldfiles = opts.oldfiles or vim.v.oldfiles
 	M.main_timer = vim.uv.new_timer()
 	---@type Sender, Receiver
 	M.tx, M.rx = channel.counter()
@@ -96,8 +128,9 @@ M.initialize = function(opts)
 	if not M.picker then
 		M.picker = nu.Picker(opts)
 	else
-		M.picker:update_config(opts)
-		M.picker:populate_files()
+		if not M.picker:update_config(opts) then
+			M.picker:populate_files()
+		end
 	end
 
 	a.run(function()
@@ -109,6 +142,36 @@ M.initialize = function(opts)
 	end)
 end
 
+local QUICKFIX_CHUNK_SIZE = 1000
+
+--- Sends the selections, or every match when nothing is selected, to the quickfix list
+---@param opts? { loclist?: boolean }
+function M.send_to_qflist(opts)
+	opts = opts or {}
+	local source = M.picker:quickfix_items("selections", 0, 1)[1] and "selections" or "matches"
+	local set_list = function(action, what)
+		if opts.loclist then
+			vim.fn.setloclist(M.original_winid, {}, action, what)
+		else
+			vim.fn.setqflist({}, action, what)
+		end
+	end
+
+	set_list(" ", { title = "Nucleo", items = {} })
+	local offset = 0
+	while true do
+		local items = M.picker:quickfix_items(source, offset, QUICKFIX_CHUNK_SIZE)
+		if #items == 0 then
+			break
+		end
+		set_list("a", { items = items })
+		offset = offset + #items
+	end
+
+	M.prompt:unmount()
+	vim.cmd(opts.loclist and "lopen" or "copen")
+end
+
 ---@param interval integer
 ---@param callback function
 function M.set_interval(interval, callback)
@@ -153,10 +216,34 @@ M.check_for_updates = vim.schedule_wrap(a.void(function()
 	end
 end))
 
+--- Entries on either side of the cursor whose previews are loaded ahead of time
+local PREFETCH_RADIUS = 2
+
+function M.prefetch_adjacent()
+	local pos = M.picker:get_cursor_pos()
+	if not pos then
+		return
+	end
+
+	-- The cursor position is relative to the window, which the adjacent entries may lie outside of
+	local cursor = M.picker:lower_bound() + pos
+	local first = math.max(cursor - PREFETCH_RADIUS, 0)
+	local files = {}
+	for idx, entry in ipairs(M.picker:matches(first, cursor + PREFETCH_RADIUS + 1 - first)) do
+		-- Lua lists are one based while match positions are zero based
+		if first + idx - 1 ~= cursor then
+			table.insert(files, entry.raw_path or entry.path)
+		end
+	end
+	M.previewer:prefetch(files)
+end
+
 M.highlight_selection = a.void(function()
 	if M.picker:total_matches() > 0 then
 		M.highlighter:highlight_selection()
-		M.previewer:render(M.picker:get_selection().path)
+		local selection = M.picker:get_selection()
+		M.previewer:render(selection.raw_path or selection.path)
+		M.prefetch_adjacent()
 	else
 		M.previewer:clear()
 	end
@@ -164,8 +251,26 @@ end)
 
 ---@class Nucleo.FilePicker.Config
 ---@field cwd? string
+---@field roots? (string|{ path: string, label?: string })[] Several directories to search, takes precedence over `cwd`
 ---@field sort_direction? "ascending"|"descending"
 ---@field git_ignore? boolean
+---@field source? "files"|"directories"
+---@field max_depth? integer How many directories deep to walk, 0 for unlimited
+---@field metadata? boolean Collect size, mtime and permissions for every entry
+---@field exclude_compressed? boolean Leave archives and compressed files out of the results, defaults to true
+---@field tiebreak? ("length"|"proximity"|"mtime"|"frecency")[] Orders equally scored matches
+---@field reference_path? string Defaults to the current buffer's file
+---@field proximity_boost? integer Score bonus for matches near `reference_path`, off by default
+---@field min_score? integer Hide matches scoring below this, off by default
+---@field empty_query_order? ("recently_opened"|"recently_modified"|"git_changed"|"open_buffers")[] Orders entries while the query is empty
+---@field open_buffers? string[] Defaults to the listed buffers
+---@field oldfiles? string[] Defaults to `v:oldfiles`, for files that weren't opened from the picker
+---@field preview_highlight? "treesitter"|"builtin" Highlight previews with treesitter, or cheaply with grammars bundled in Rust
+---@field preview_mode? "file"|"diff" Preview file contents, or the working tree diff against HEAD
+---@field preview_query_match? "literal"|"fuzzy" How the query is highlighted in previews
+---@field cache? boolean Keep walked roots on disk so results show up before the walk finishes
+---@field cache_dir? string Defaults to `stdpath("cache")/nucleo`
+---@field frecency_path? string Where opened files are remembered between sessions, defaults to `stdpath("data")/nucleo/frecency`
 
 ---@param opts? Nucleo.FilePicker.Config
 M.find = function(opts)
@@ -173,7 +278,11 @@ M.find = function(opts)
 	M.original_cursor = api.nvim_win_get_cursor(M.original_winid)
 
 	M.results = Results()
-	M.previewer = Previewer()
+	M.previewer = Previewer({
+		highlight = opts and opts.preview_highlight,
+		mode = opts and opts.preview_mode,
+		query_match = opts and opts.preview_query_match,
+	})
 	M.initialize(opts)
 
 	M.highlighter = Highlighter({
@@ -210,8 +319,10 @@ M.find = function(opts)
 						api.nvim_set_current_win(M.original_winid)
 					end
 				else
-					local selection = M.picker:get_selection().path
+					local entry = M.picker:get_selection()
+					local selection = entry.raw_path or entry.path
 					log.info("Input Submitted: " .. selection)
+					M.picker:record_open(entry.path)
 
 					if M.original_winid then
 						api.nvim_set_current_win(M.original_winid)
@@ -282,6 +393,14 @@ M.find = function(opts)
 		M.tx.send()
 	end, { noremap = true })
 
+	M.prompt:map("i", { "<C-q>" }, function()
+		M.send_to_qflist()
+	end, { noremap = true })
+
+	M.prompt:map("i", { "<M-q>" }, function()
+		M.send_to_qflist({ loclist = true })
+	end, { noremap = true })
+
 	M.prompt:map("i", { "<C-s>" }, function()
 		extensions.flash.jump(M.picker, M.results)
 		M.tx.send()
@@ -3,8 +3,27 @@ local api = vim.api
 
 local Previewer = Popup:extend("Previewer")
 
+local ns_highlight = api.nvim_create_namespace("nucleo_preview_highlight")
+local ns_target = api.nvim_create_namespace("nucleo_preview_target")
+local ns_query = api.nvim_create_namespace("nucleo_preview_query")
+
+---@class Nucleo.Previewer.Options
+---@field highlight? "treesitter"|"builtin" Highlight with treesitter or with the grammars bundled in the Rust module
+---@field mode? "file"|"diff" Preview the file contents, or its changes against HEAD
+---@field query_match? "literal"|"fuzzy" Highlight each word of the query, or the characters of lines that fuzzy match it
+
+---@param popup_options? table|Nucleo.Previewer.Options
 function Previewer:init(popup_options)
-	local options = vim.tbl_deep_extend("force", popup_options or {}, {
+	popup_options = popup_options or {}
+	self.highlight = popup_options.highlight or "treesitter"
+	self.mode = popup_options.mode or "file"
+	self.query_match = popup_options.query_match or "literal"
+	self.query = ""
+	popup_options.highlight = nil
+	popup_options.mode = nil
+	popup_options.query_match = nil
+
+	local options = vim.tbl_deep_extend("force", popup_options, {
 		border = "rounded",
 		focusable = false,
 		style = "minimal",
@@ -27,31 +46,185 @@ function Previewer:clear()
 	api.nvim_buf_set_lines(self.bufnr, 0, -1, false, {})
 end
 
-function Previewer:render(file)
-	if self.winid then
-		local height = api.nvim_win_get_height(self.winid)
-		local lines = self.previewer:preview_file(file, 0, height)
-		local content = vim.split(lines, "\n")
-		api.nvim_buf_set_lines(self.bufnr, 0, -1, false, content)
+--- Shows the encoding and line ending of the previewed file in the border, if it has them
+---@param encoding? string
+---@param line_ending? string
+function Previewer:set_header(encoding, line_ending)
+	if not encoding or not line_ending then
+		return self.border:set_text("top", "")
+	end
+	self.border:set_text("top", string.format(" %s · %s ", encoding, line_ending), "right")
+end
+
+--- The picker query, highlighted in the previews rendered from now on
+---@param query string
+function Previewer:set_query(query)
+	self.query = query
+end
+
+--- Highlights where the query matches in the previewed `contents`
+---@param contents string
+function Previewer:highlight_query(contents)
+	api.nvim_buf_clear_namespace(self.bufnr, ns_query, 0, -1)
+	if self.query == "" or self.mode == "diff" then
+		return
+	end
+
+	local ranges = self.previewer:query_matches(contents, self.query, self.query_match)
+	for _, range in ipairs(ranges) do
+		api.nvim_buf_set_extmark(self.bufnr, ns_query, range.line, range.start_col, {
+			end_col = range.end_col,
+			hl_group = "Search",
+		})
+	end
+end
+
+---@param preview { contents: string, encoding?: string, line_ending?: string, highlights: { line: integer, start_col: integer, end_col: integer, hl_group: string }[] }
+function Previewer:show_highlighted(preview)
+	self:set_header(preview.encoding, preview.line_ending)
+	api.nvim_buf_set_lines(self.bufnr, 0, -1, false, vim.split(preview.contents, "\n"))
+	api.nvim_buf_clear_namespace(self.bufnr, ns_highlight, 0, -1)
+	api.nvim_buf_clear_namespace(self.bufnr, ns_target, 0, -1)
+
+	for _, span in ipairs(preview.highlights) do
+		api.nvim_buf_set_extmark(self.bufnr, ns_highlight, span.line, span.start_col, {
+			end_col = span.end_col,
+			hl_group = span.hl_group,
+		})
+	end
+end
+
+--- Previews the lines around a position, highlighting the target line and its matches
+---@param file string
+---@param target { line: integer, col?: integer, text?: string } Zero based line and byte column in the file
+function Previewer:render_target(file, target)
+	if not self.winid then
+		return
+	end
+
+	local kind = self:kind()
+	local pending = self.previewer:preview_target(file, {
+		line = target.line,
+		col = target.col,
+		text = target.text,
+		height = api.nvim_win_get_height(self.winid),
+	}, kind)
+	self:await(pending, function()
+		local preview = pending:preview()
+		if kind == "highlighted" then
+			self:show_highlighted(preview)
+		else
+			self:set_header(preview.encoding, preview.line_ending)
+			self:show(file, preview.contents)
+			api.nvim_buf_clear_namespace(self.bufnr, ns_highlight, 0, -1)
+		end
+		self:highlight_query(preview.contents)
+
+		api.nvim_buf_set_extmark(self.bufnr, ns_target, preview.target_line, 0, {
+			line_hl_group = "CursorLine",
+		})
+		for _, range in ipairs(preview.matches) do
+			api.nvim_buf_set_extmark(self.bufnr, ns_target, range.line, range.start_col, {
+				end_col = range.end_col,
+				hl_group = "Search",
+			})
+		end
+	end)
+end
 
-		local line_count = api.nvim_buf_line_count(self.bufnr)
-		if line_count == 0 then
+---@param file string
+---@param lines string
+function Previewer:show(file, lines)
+	local content = vim.split(lines, "\n")
+	api.nvim_buf_set_lines(self.bufnr, 0, -1, false, content)
+	api.nvim_buf_clear_namespace(self.bufnr, ns_target, 0, -1)
+
+	local line_count = api.nvim_buf_line_count(self.bufnr)
+	if line_count == 0 then
+		return
+	end
+
+	vim.schedule(function()
+		local name = vim.fs.basename(file)
+		local ft = vim.filetype.match({ filename = name, content = content })
+		if not ft or ft == "" then
 			return
 		end
 
-		vim.schedule(function()
-			local name = vim.fs.basename(file)
-			local ft = vim.filetype.match({ filename = name, content = content })
-			if not ft or ft == "" then
-				return
-			end
+		local lang = vim.treesitter.language.get_lang(ft)
+		if lang and has_ts_parser(lang) then
+			return vim.treesitter.start(self.bufnr, lang)
+		end
+	end)
+end
+
+--- The kind of preview the Rust module loads for the configured mode and highlighting
+---@return "plain"|"highlighted"|"diff"
+function Previewer:kind()
+	if self.mode == "diff" then
+		return "diff"
+	elseif self.highlight == "builtin" then
+		return "highlighted"
+	end
+	return "plain"
+end
+
+--- How often a preview still loading in the background is checked on, in milliseconds
+local PENDING_POLL_INTERVAL = 10
+
+--- Calls `on_ready` once `pending` has loaded, unless another preview was rendered in the meantime
+---@param pending { ready: fun(self): boolean }
+---@param on_ready fun()
+function Previewer:await(pending, on_ready)
+	self.pending = pending
+
+	local function poll()
+		if self.pending ~= pending or not self.winid then
+			return
+		end
+		if pending:ready() then
+			self.pending = nil
+			return on_ready()
+		end
+		vim.defer_fn(poll, PENDING_POLL_INTERVAL)
+	end
+	poll()
+end
+
+---@param file string
+---@param target? { line: integer, col?: integer, text?: string } Position to center the preview on
+function Previewer:render(file, target)
+	if self.winid and target and self.mode ~= "diff" then
+		return self:render_target(file, target)
+	end
 
-			local lang = vim.treesitter.language.get_lang(ft)
-			if lang and has_ts_parser(lang) then
-				return vim.treesitter.start(self.bufnr, lang)
+	if self.winid then
+		local height = api.nvim_win_get_height(self.winid)
+		local kind = self:kind()
+		local pending = self.previewer:preview_async(file, 0, height, kind)
+		self:await(pending, function()
+			if kind == "plain" then
+				self:set_header(pending:encoding(), pending:line_ending())
+				self:show(file, pending:contents())
+			else
+				self:show_highlighted({
+					contents = pending:contents(),
+					encoding = pending:encoding(),
+					line_ending = pending:line_ending(),
+					highlights = pending:highlights(),
+				})
 			end
+			self:highlight_query(pending:contents())
 		end)
 	end
 end
 
+--- Loads the previews of `files` in the background so that moving to them is instant
+---@param files string[]
+function Previewer:prefetch(files)
+	if self.winid and #files > 0 then
+		self.previewer:prefetch(files, 0, api.nvim_win_get_height(self.winid), self:kind())
+	end
+end
+
 return Previewer
@@ -0,0 +1,114 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use crate::format::{format_date_time, format_size, format_timestamp};
+
+/// Members listed before an archive preview is cut off
+const MAX_ARCHIVE_MEMBERS: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".zip") || name.ends_with(".jar") {
+            Some(ArchiveKind::Zip)
+        } else {
+            None
+        }
+    }
+}
+
+struct Member {
+    name: String,
+    size: u64,
+    /// Formatted as `YYYY-MM-DD HH:MM`
+    mtime: String,
+}
+
+fn zip_members(file: File) -> zip::result::ZipResult<(Vec<Member>, bool)> {
+    let mut archive = zip::ZipArchive::new(BufReader::new(file))?;
+    let mut members = Vec::new();
+    for index in 0..archive.len().min(MAX_ARCHIVE_MEMBERS) {
+        // The raw entry skips setting up decompression, which listing doesn't need
+        let member = archive.by_index_raw(index)?;
+        let mtime = member.last_modified();
+        members.push(Member {
+            name: member.name().to_string(),
+            size: member.size(),
+            mtime: format_date_time(
+                mtime.year().into(),
+                mtime.month().into(),
+                mtime.day().into(),
+                mtime.hour().into(),
+                mtime.minute().into(),
+            ),
+        });
+    }
+
+    Ok((members, archive.len() > MAX_ARCHIVE_MEMBERS))
+}
+
+fn tar_members(reader: impl Read) -> std::io::Result<(Vec<Member>, bool)> {
+    let mut archive = tar::Archive::new(reader);
+    let mut members = Vec::new();
+    for entry in archive.entries()? {
+        if members.len() == MAX_ARCHIVE_MEMBERS {
+            return Ok((members, true));
+        }
+        let entry = entry?;
+        let header = entry.header();
+        members.push(Member {
+            name: entry.path()?.to_string_lossy().to_string(),
+            size: header.size().unwrap_or_default(),
+            mtime: header.mtime().map(format_timestamp).unwrap_or_default(),
+        });
+    }
+
+    Ok((members, false))
+}
+
+/// One line per member with its size and mtime, without extracting anything
+pub fn list_members(path: &Path, kind: ArchiveKind) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let listed = match kind {
+        ArchiveKind::Zip => zip_members(file).map_err(|err| err.to_string()),
+        ArchiveKind::Tar => tar_members(BufReader::new(file)).map_err(|err| err.to_string()),
+        ArchiveKind::TarGz => tar_members(flate2::read::GzDecoder::new(BufReader::new(file)))
+            .map_err(|err| err.to_string()),
+    };
+    let (members, truncated) = match listed {
+        Ok(listed) => listed,
+        Err(err) => {
+            log::warn!("Failed listing archive {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    let mut lines = members
+        .iter()
+        .map(|member| {
+            format!(
+                "{:>8}  {:16}  {}",
+                format_size(member.size),
+                member.mtime,
+                member.name
+            )
+        })
+        .collect::<Vec<_>>();
+    if truncated {
+        lines.push("…".to_string());
+    }
+
+    Some(lines.join("\n"))
+}
@@ -0,0 +1,70 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+
+/// A map that drops its oldest entries once it holds `CAPACITY` of them
+#[derive(Debug)]
+pub struct BoundedCache<K, V, const CAPACITY: usize> {
+    entries: HashMap<K, V>,
+    /// Keys in insertion order, for evicting the oldest
+    order: VecDeque<K>,
+}
+
+impl<K: Hash + Eq + Clone, V: Clone, const CAPACITY: usize> BoundedCache<K, V, CAPACITY> {
+    /// The value for `key`, and whether it was just created with `init`
+    pub fn get_or_insert_with(&mut self, key: K, init: impl FnOnce() -> V) -> (V, bool) {
+        if let Some(value) = self.entries.get(&key) {
+            return (value.clone(), false);
+        }
+
+        if self.order.len() >= CAPACITY {
+            if let Some(oldest) = self.order.pop_front() {
+                self.entries.remove(&oldest);
+            }
+        }
+        let value = init();
+        self.entries.insert(key.clone(), value.clone());
+        self.order.push_back(key);
+
+        (value, true)
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+impl<K, V, const CAPACITY: usize> Default for BoundedCache<K, V, CAPACITY> {
+    fn default() -> Self {
+        Self {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_the_oldest_entries() {
+        let mut cache = BoundedCache::<u32, u32, 2>::default();
+        assert_eq!(cache.get_or_insert_with(1, || 10), (10, true));
+        assert_eq!(cache.get_or_insert_with(2, || 20), (20, true));
+        // Lookups don't refresh an entry's age
+        assert_eq!(cache.get_or_insert_with(1, || 11), (10, false));
+
+        assert_eq!(cache.get_or_insert_with(3, || 30), (30, true));
+        assert_eq!(cache.get_or_insert_with(1, || 12), (12, true));
+        assert_eq!(cache.get_or_insert_with(3, || 31), (30, false));
+    }
+
+    #[test]
+    fn clears_every_entry() {
+        let mut cache = BoundedCache::<u32, u32, 2>::default();
+        cache.get_or_insert_with(1, || 10);
+        cache.clear();
+        assert_eq!(cache.get_or_insert_with(1, || 11), (11, true));
+    }
+}
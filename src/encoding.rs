@@ -0,0 +1,309 @@
+use serde::Serialize;
+use strum::Display;
+
+/// Bytes sampled when guessing whether a file without a BOM is UTF-16
+pub const SNIFF_LENGTH: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Display)]
+pub enum Encoding {
+    #[serde(rename = "utf-8")]
+    #[strum(serialize = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-8-bom")]
+    #[strum(serialize = "utf-8-bom")]
+    Utf8Bom,
+    #[serde(rename = "utf-16le")]
+    #[strum(serialize = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    #[strum(serialize = "utf-16be")]
+    Utf16Be,
+    #[serde(rename = "latin-1")]
+    #[strum(serialize = "latin-1")]
+    Latin1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+/// Text transcoded to UTF-8 with `\n` line endings, along with what the file used
+#[derive(Debug, Clone)]
+pub struct DecodedText {
+    pub text: String,
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+}
+
+/// Guesses UTF-16 from the NUL bytes that ASCII text leaves in every other byte
+fn sniff_utf16(bytes: &[u8]) -> Option<Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LENGTH) & !1];
+    if sample.is_empty() {
+        return None;
+    }
+
+    let pairs = sample.len() / 2;
+    let even_nuls = sample.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_nuls = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|byte| **byte == 0)
+        .count();
+    let mostly = |count: usize| count * 10 >= pairs * 4;
+    let rarely = |count: usize| count * 20 <= pairs;
+
+    if mostly(odd_nuls) && rarely(even_nuls) {
+        Some(Encoding::Utf16Le)
+    } else if mostly(even_nuls) && rarely(odd_nuls) {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Whether the file starting with `head` is UTF-16, where a newline isn't a single `\n` byte
+pub fn is_utf16(head: &[u8]) -> bool {
+    head.starts_with(b"\xFF\xFE") || head.starts_with(b"\xFE\xFF") || sniff_utf16(head).is_some()
+}
+
+/// The length of the byte order mark `head` starts with, if any
+pub fn bom_len(head: &[u8]) -> usize {
+    if head.starts_with(b"\xEF\xBB\xBF") {
+        3
+    } else if head.starts_with(b"\xFF\xFE") || head.starts_with(b"\xFE\xFF") {
+        2
+    } else {
+        0
+    }
+}
+
+/// Converts a byte column of a line as stored in the file to a byte column of the same line
+/// decoded to UTF-8. `bom_len` is the length of the byte order mark before the line, which is
+/// only non zero for the first line of the file.
+pub fn decoded_col(line: &str, raw_col: usize, encoding: Encoding, bom_len: usize) -> usize {
+    let raw_col = raw_col.saturating_sub(bom_len);
+
+    match encoding {
+        Encoding::Utf8 | Encoding::Utf8Bom => {
+            let col = raw_col.min(line.len());
+            (0..=col)
+                .rev()
+                .find(|idx| line.is_char_boundary(*idx))
+                .unwrap_or_default()
+        }
+        // Every byte is one character
+        Encoding::Latin1 => line
+            .char_indices()
+            .nth(raw_col)
+            .map_or(line.len(), |(idx, _)| idx),
+        // Every two bytes are one UTF-16 code unit
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let mut units = 0;
+            line.char_indices()
+                .find(|(_, c)| {
+                    units += c.len_utf16();
+                    units > raw_col / 2
+                })
+                .map_or(line.len(), |(idx, _)| idx)
+        }
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]));
+
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// The most common line ending in `text`, CRLF wins ties since it contains a LF
+fn detect_line_ending(text: &str) -> LineEnding {
+    let crlf = text.matches("\r\n").count();
+    let cr = text.matches('\r').count() - crlf;
+    let lf = text.matches('\n').count() - crlf;
+
+    if crlf > 0 && crlf >= lf && crlf >= cr {
+        LineEnding::Crlf
+    } else if cr > lf {
+        LineEnding::Cr
+    } else {
+        LineEnding::Lf
+    }
+}
+
+/// Decodes by BOM, then as UTF-16 by sniffing, then as UTF-8 and finally as Latin-1.
+/// Returns `None` for binary files, which contain NULs without looking like UTF-16.
+pub fn decode(bytes: Vec<u8>) -> Option<DecodedText> {
+    let (text, encoding) = if let Some(rest) = bytes.strip_prefix(b"\xEF\xBB\xBF") {
+        (String::from_utf8_lossy(rest).to_string(), Encoding::Utf8Bom)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFF\xFE") {
+        (decode_utf16(rest, u16::from_le_bytes), Encoding::Utf16Le)
+    } else if let Some(rest) = bytes.strip_prefix(b"\xFE\xFF") {
+        (decode_utf16(rest, u16::from_be_bytes), Encoding::Utf16Be)
+    } else if let Some(encoding) = sniff_utf16(&bytes) {
+        let from_bytes = match encoding {
+            Encoding::Utf16Be => u16::from_be_bytes,
+            _ => u16::from_le_bytes,
+        };
+        (decode_utf16(&bytes, from_bytes), encoding)
+    } else {
+        match String::from_utf8(bytes) {
+            Ok(text) => (text, Encoding::Utf8),
+            Err(err) if err.as_bytes().contains(&0) => return None,
+            // Every byte is a valid Latin-1 character with the same code point
+            Err(err) => (
+                err.as_bytes().iter().map(|byte| *byte as char).collect(),
+                Encoding::Latin1,
+            ),
+        }
+    };
+
+    let line_ending = detect_line_ending(&text);
+    let text = match line_ending {
+        LineEnding::Lf if !text.contains('\r') => text,
+        _ => text.replace("\r\n", "\n").replace('\r', "\n"),
+    };
+
+    Some(DecodedText {
+        text,
+        encoding,
+        line_ending,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+
+    fn utf16be(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_be_bytes).collect()
+    }
+
+    fn decoded(bytes: Vec<u8>) -> (String, Encoding, LineEnding) {
+        let decoded = decode(bytes).unwrap();
+        (decoded.text, decoded.encoding, decoded.line_ending)
+    }
+
+    #[test]
+    fn decodes_by_bom() {
+        assert_eq!(
+            decoded(b"\xEF\xBB\xBFh\xC3\xA9".to_vec()),
+            ("hé".to_string(), Encoding::Utf8Bom, LineEnding::Lf)
+        );
+        assert_eq!(
+            decoded([b"\xFF\xFE".to_vec(), utf16le("hé€\n")].concat()),
+            ("hé€\n".to_string(), Encoding::Utf16Le, LineEnding::Lf)
+        );
+        assert_eq!(
+            decoded([b"\xFE\xFF".to_vec(), utf16be("hé€\n")].concat()),
+            ("hé€\n".to_string(), Encoding::Utf16Be, LineEnding::Lf)
+        );
+        assert_eq!(bom_len(b"\xEF\xBB\xBFh"), 3);
+        assert_eq!(bom_len(b"\xFF\xFEh\x00"), 2);
+        assert_eq!(bom_len(b"\xFE\xFF\x00h"), 2);
+        assert_eq!(bom_len(b"hello"), 0);
+    }
+
+    #[test]
+    fn sniffs_utf16_without_bom() {
+        assert_eq!(
+            decoded(utf16le("fn main() {}\n")),
+            (
+                "fn main() {}\n".to_string(),
+                Encoding::Utf16Le,
+                LineEnding::Lf
+            )
+        );
+        assert_eq!(
+            decoded(utf16be("fn main() {}\n")),
+            (
+                "fn main() {}\n".to_string(),
+                Encoding::Utf16Be,
+                LineEnding::Lf
+            )
+        );
+        assert!(is_utf16(&utf16le("fn main() {}\n")));
+        assert!(!is_utf16(b"fn main() {}\n"));
+    }
+
+    #[test]
+    fn falls_back_to_latin1() {
+        assert_eq!(decoded(b"caf\xE9\n".to_vec()).0, "café\n");
+        assert_eq!(decoded(b"caf\xE9\n".to_vec()).1, Encoding::Latin1);
+        assert_eq!(decoded(b"caf\xC3\xA9\n".to_vec()).1, Encoding::Utf8);
+    }
+
+    #[test]
+    fn rejects_binary() {
+        assert!(decode(b"\x7FELF\x02\x01\x01\x00\x00\x00\x00\x00\xFF".to_vec()).is_none());
+    }
+
+    #[test]
+    fn normalizes_line_endings() {
+        assert_eq!(
+            decoded(b"a\r\nb\r\n".to_vec()),
+            ("a\nb\n".to_string(), Encoding::Utf8, LineEnding::Crlf)
+        );
+        assert_eq!(
+            decoded(b"a\rb\r".to_vec()),
+            ("a\nb\n".to_string(), Encoding::Utf8, LineEnding::Cr)
+        );
+        // The odd CRLF is normalized as well when most lines end in LF
+        assert_eq!(
+            decoded(b"a\nb\nc\r\n".to_vec()),
+            ("a\nb\nc\n".to_string(), Encoding::Utf8, LineEnding::Lf)
+        );
+        assert_eq!(
+            decoded(utf16le("a\r\nb\r\n")),
+            ("a\nb\n".to_string(), Encoding::Utf16Le, LineEnding::Crlf)
+        );
+    }
+
+    #[test]
+    fn crlf_wins_ties() {
+        assert_eq!(decoded(b"a\r\nb\nc".to_vec()).2, LineEnding::Crlf);
+        assert_eq!(decoded(b"a\r\nb\rc".to_vec()).2, LineEnding::Crlf);
+    }
+
+    #[test]
+    fn converts_utf8_columns() {
+        // The `l` after the two byte `é`
+        assert_eq!(decoded_col("héllo", 3, Encoding::Utf8, 0), 3);
+        assert_eq!(decoded_col("héllo", 6, Encoding::Utf8Bom, 3), 3);
+        // Columns inside a character round down to its start
+        assert_eq!(decoded_col("héllo", 2, Encoding::Utf8, 0), 1);
+        assert_eq!(decoded_col("héllo", 100, Encoding::Utf8, 0), 6);
+    }
+
+    #[test]
+    fn converts_latin1_columns() {
+        // `é` is a single byte in the file and two once decoded
+        assert_eq!(decoded_col("héllo", 2, Encoding::Latin1, 0), 3);
+        assert_eq!(decoded_col("héllo", 100, Encoding::Latin1, 0), 6);
+    }
+
+    #[test]
+    fn converts_utf16_columns() {
+        for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+            // The `x` after three code units, whose UTF-8 lengths are 1, 2 and 3 bytes
+            assert_eq!(decoded_col("aé€x", 6, encoding, 0), 6);
+            assert_eq!(decoded_col("aé€x", 8, encoding, 2), 6);
+            // Characters outside the BMP are two code units
+            assert_eq!(decoded_col("😀x", 4, encoding, 0), 4);
+            assert_eq!(decoded_col("😀x", 6, encoding, 2), 4);
+        }
+    }
+}
@@ -0,0 +1,56 @@
+use std::io;
+
+use thiserror::Error as ThisError;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, ThisError)]
+pub enum Error {
+    #[error("{path} is not inside the picker root {root}")]
+    OutsideRoot { path: String, root: String },
+
+    #[error("Failed to read the current directory: {0}")]
+    CurrentDir(#[source] io::Error),
+
+    #[error("Failed to start the file searcher: {0}")]
+    Runtime(#[source] io::Error),
+
+    #[error("Invalid file type definition: {0}")]
+    FileTypes(#[from] ignore::Error),
+
+    #[error("Git error: {0}")]
+    Git(#[from] git2::Error),
+
+    #[error("Selection index {0} is out of bounds")]
+    SelectionOutOfBounds(usize),
+
+    #[error("Invalid log level: {0}")]
+    InvalidLogLevel(String),
+
+    #[error("Failed to write index cache {path}: {source}")]
+    IndexCache {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to write frecency file {path}: {source}")]
+    Frecency {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to open log file {path}: {source}")]
+    LogFile {
+        path: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl From<Error> for mlua::Error {
+    fn from(err: Error) -> Self {
+        mlua::Error::external(err)
+    }
+}
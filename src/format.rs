@@ -0,0 +1,47 @@
+/// Sizes the way `ls -h` prints them
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "K", "M", "G", "T"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{}{}", size, UNITS[0]),
+        _ => format!("{:.1}{}", value, UNITS[unit]),
+    }
+}
+
+/// Converts days since the Unix epoch to a (year, month, day) civil date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Formats a date and time as `YYYY-MM-DD HH:MM`
+pub fn format_date_time(year: i64, month: u32, day: u32, hour: u32, minute: u32) -> String {
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year, month, day, hour, minute
+    )
+}
+
+/// Formats seconds since the Unix epoch as `YYYY-MM-DD HH:MM` in UTC
+pub fn format_timestamp(secs: u64) -> String {
+    let secs = secs as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let time = secs.rem_euclid(86_400) as u32;
+
+    format_date_time(year, month, day, time / 3600, (time % 3600) / 60)
+}
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+
+/// Visits remembered per path, older ones stop contributing to the score
+const MAX_VISITS: usize = 10;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// Tracks how often and how recently paths were opened from the picker
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Frecency {
+    visits: HashMap<String, VecDeque<u64>>,
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+impl Frecency {
+    pub fn record(&mut self, path: String, timestamp: u64) {
+        let visits = self.visits.entry(path).or_default();
+        if visits.len() >= MAX_VISITS {
+            visits.pop_front();
+        }
+        visits.push_back(timestamp);
+    }
+
+    pub fn last_visit(&self, path: &str) -> Option<u64> {
+        self.visits
+            .get(path)
+            .and_then(|visits| visits.back().copied())
+    }
+
+    /// Sums a weight per remembered visit, recent visits weigh more
+    pub fn score(&self, path: &str, now: u64) -> u64 {
+        let Some(visits) = self.visits.get(path) else {
+            return 0;
+        };
+
+        visits
+            .iter()
+            .map(|timestamp| match now.saturating_sub(*timestamp) {
+                age if age < 4 * HOUR => 100,
+                age if age < DAY => 80,
+                age if age < 3 * DAY => 60,
+                age if age < 7 * DAY => 40,
+                age if age < 30 * DAY => 20,
+                _ => 10,
+            })
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.visits.is_empty()
+    }
+
+    /// Reads the visits saved by earlier sessions, starting out empty when there are none
+    pub fn load(path: &Path) -> Self {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(_) => return Self::default(),
+        };
+
+        bincode::deserialize_from(BufReader::new(file)).unwrap_or_else(|err| {
+            log::warn!("Ignoring unreadable frecency file: {}", err);
+            Self::default()
+        })
+    }
+
+    /// Writes to a temporary file first so that a reader never sees a partial file
+    pub fn save(&self, path: &Path) -> Result<()> {
+        crate::path::write_atomically(path, self).map_err(|source| Error::Frecency {
+            path: path.to_string_lossy().to_string(),
+            source,
+        })
+    }
+}
@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use git2::{DiffOptions, Patch, Repository, StatusOptions};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::error::Result;
+
+/// Absolute paths of the modified, staged and untracked files in the repository containing `dir`
+pub fn changed_files(dir: &Path) -> Result<HashSet<String>> {
+    let repo = Repository::discover(dir)?;
+    let Some(workdir) = repo.workdir() else {
+        return Ok(HashSet::new());
+    };
+
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    let statuses = repo.statuses(Some(&mut options))?;
+
+    Ok(statuses
+        .iter()
+        .map(|entry| {
+            workdir
+                .join(crate::path::from_bytes(entry.path_bytes()))
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiffLineKind {
+    Hunk,
+    Context,
+    Added,
+    Removed,
+}
+
+/// How a file on disk differs from its version in HEAD
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileDiff {
+    Unchanged,
+    Binary,
+    /// Either version is too big to read whole, see `line_index::STREAM_THRESHOLD`
+    TooLarge,
+    Lines(Vec<(DiffLineKind, String)>),
+}
+
+/// Repositories opened for diff previews, by the path of their git directory
+static REPOSITORIES: Lazy<Mutex<HashMap<PathBuf, Arc<Mutex<Repository>>>>> =
+    Lazy::new(Mutex::default);
+
+/// The repository containing `dir`, opened once and reused by every preview inside it.
+/// Finding the git directory only looks at the file system, opening the repository is what's slow.
+fn repository(dir: &Path) -> Result<Arc<Mutex<Repository>>> {
+    let git_dir = Repository::discover_path(dir, std::iter::empty::<&OsStr>())?;
+    let mut repositories = REPOSITORIES.lock();
+    if let Some(repo) = repositories.get(&git_dir) {
+        return Ok(repo.clone());
+    }
+
+    let repo = Arc::new(Mutex::new(Repository::open(&git_dir)?));
+    repositories.insert(git_dir, repo.clone());
+
+    Ok(repo)
+}
+
+/// Diffs the file at `path` against the blob in HEAD, an untracked file is entirely added
+pub fn diff_against_head(path: &Path) -> Result<FileDiff> {
+    let repo = repository(path.parent().unwrap_or(path))?;
+    let repo = repo.lock();
+    let workdir = match repo.workdir() {
+        Some(workdir) => workdir.canonicalize().unwrap_or(workdir.to_path_buf()),
+        None => return Ok(FileDiff::Unchanged),
+    };
+    let path = path.canonicalize().unwrap_or(path.to_path_buf());
+    let relative = path.strip_prefix(&workdir).unwrap_or(&path);
+
+    let too_large = |len: u64| len > crate::line_index::STREAM_THRESHOLD;
+    if fs::metadata(&path).is_ok_and(|metadata| too_large(metadata.len())) {
+        return Ok(FileDiff::TooLarge);
+    }
+    // The size of the blob comes from its header so that a huge one is never loaded
+    let head_blob = match repo.head().and_then(|head| head.peel_to_tree()) {
+        Ok(tree) => match tree.get_path(relative) {
+            Ok(entry) if too_large(repo.odb()?.read_header(entry.id())?.0 as u64) => {
+                return Ok(FileDiff::TooLarge);
+            }
+            Ok(entry) => Some(repo.find_blob(entry.id())?),
+            Err(_) => None,
+        },
+        // An unborn branch has no HEAD to compare against
+        Err(_) => None,
+    };
+    let contents = fs::read(&path).unwrap_or_default();
+
+    if head_blob.as_ref().is_some_and(|blob| blob.is_binary()) || contents.contains(&0) {
+        return Ok(
+            match head_blob.is_some_and(|blob| blob.content() == contents.as_slice()) {
+                true => FileDiff::Unchanged,
+                false => FileDiff::Binary,
+            },
+        );
+    }
+
+    let mut options = DiffOptions::new();
+    options.context_lines(3);
+    let patch = match &head_blob {
+        Some(blob) => Patch::from_blob_and_buffer(
+            blob,
+            Some(relative),
+            &contents,
+            Some(relative),
+            Some(&mut options),
+        )?,
+        None => Patch::from_buffers(&[], None, &contents, Some(relative), Some(&mut options))?,
+    };
+
+    let mut lines = Vec::new();
+    for hunk_idx in 0..patch.num_hunks() {
+        let (hunk, line_count) = patch.hunk(hunk_idx)?;
+        let header = String::from_utf8_lossy(hunk.header());
+        lines.push((DiffLineKind::Hunk, header.trim_end().to_string()));
+
+        for line_idx in 0..line_count {
+            let line = patch.line_in_hunk(hunk_idx, line_idx)?;
+            let kind = match line.origin() {
+                '+' => DiffLineKind::Added,
+                '-' => DiffLineKind::Removed,
+                ' ' => DiffLineKind::Context,
+                // Markers for a missing newline at the end of the file
+                _ => continue,
+            };
+            let content = String::from_utf8_lossy(line.content());
+            lines.push((
+                kind,
+                format!(
+                    "{}{}",
+                    line.origin(),
+                    content.trim_end_matches(['\n', '\r'])
+                ),
+            ));
+        }
+    }
+
+    Ok(match lines.is_empty() {
+        true => FileDiff::Unchanged,
+        false => FileDiff::Lines(lines),
+    })
+}
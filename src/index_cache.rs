@@ -0,0 +1,147 @@
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::injector::WalkOptions;
+use crate::picker::{Entry, EntryKind, FileMetadata};
+
+/// Bumped whenever the layout of the cache file changes
+const CACHE_VERSION: u32 = 2;
+
+/// An entry as found by the walker, before it is turned into a picker entry
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedEntry {
+    #[serde(with = "serde_bytes")]
+    pub path: Vec<u8>,
+    pub kind: EntryKind,
+    /// Not cached since files change without touching their directory, see `read_metadata`
+    #[serde(skip)]
+    pub metadata: Option<FileMetadata>,
+}
+
+impl CachedEntry {
+    /// Reads the metadata of a cached entry from disk
+    pub fn read_metadata(&mut self) {
+        self.metadata = FileMetadata::from_path(&crate::path::from_bytes(&self.path));
+    }
+
+    pub fn into_entry<T: Entry>(self, cwd: &str, label: Option<&String>) -> Result<T> {
+        let entry = T::from_path(&crate::path::from_bytes(&self.path), Some(cwd.to_string()))?
+            .with_kind(self.kind);
+        let entry = match label {
+            Some(label) => entry.with_root(label.clone()),
+            None => entry,
+        };
+
+        Ok(match self.metadata {
+            Some(metadata) => entry.with_metadata(metadata),
+            None => entry,
+        })
+    }
+}
+
+/// A walked directory and its mtime, any file added or removed inside it changes the mtime
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CachedDirectory {
+    #[serde(with = "serde_bytes")]
+    pub path: Vec<u8>,
+    pub mtime: Option<(u64, u32)>,
+}
+
+impl CachedDirectory {
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: crate::path::to_bytes(path),
+            mtime: mtime(path),
+        }
+    }
+
+    fn is_fresh(&self) -> bool {
+        mtime(&crate::path::from_bytes(&self.path)) == self.mtime
+    }
+}
+
+fn mtime(path: &Path) -> Option<(u64, u32)> {
+    let duration = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()?;
+
+    Some((duration.as_secs(), duration.subsec_nanos()))
+}
+
+/// The walked entries of one root, stored on disk so the next picker can show them immediately
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexCache {
+    version: u32,
+    key: String,
+    pub directories: Vec<CachedDirectory>,
+    pub entries: Vec<CachedEntry>,
+}
+
+impl IndexCache {
+    pub fn new(key: String, directories: Vec<CachedDirectory>, entries: Vec<CachedEntry>) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            key,
+            directories,
+            entries,
+        }
+    }
+
+    /// Identifies a root walked with particular options
+    pub fn key(root: &str, options: &WalkOptions) -> String {
+        format!(
+            "{}\0{}\0{}\0{:?}\0{}\0{}",
+            root,
+            options.source,
+            options.git_ignore,
+            options.max_depth,
+            options.metadata,
+            options.exclude_compressed
+        )
+    }
+
+    fn file_path(dir: &Path, key: &str) -> PathBuf {
+        dir.join(format!("{:016x}.index", fnv1a(key.as_bytes())))
+    }
+
+    pub fn load(dir: &Path, key: &str) -> Option<Self> {
+        let file = File::open(Self::file_path(dir, key)).ok()?;
+        let cache: Self = match bincode::deserialize_from(BufReader::new(file)) {
+            Ok(cache) => cache,
+            Err(err) => {
+                log::warn!("Ignoring unreadable index cache: {}", err);
+                return None;
+            }
+        };
+
+        (cache.version == CACHE_VERSION && cache.key == key).then_some(cache)
+    }
+
+    /// Writes to a temporary file first so that readers never see a partial cache
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        let path = Self::file_path(dir, &self.key);
+        crate::path::write_atomically(&path, self).map_err(|source| Error::IndexCache {
+            path: path.to_string_lossy().to_string(),
+            source,
+        })
+    }
+
+    /// Whether no directory gained or lost entries since the cache was written
+    pub fn is_fresh(&self) -> bool {
+        self.directories.iter().all(CachedDirectory::is_fresh)
+    }
+}
+
+/// Stable across builds, unlike the standard library's hashers
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,14 +1,113 @@
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
 
 use crossbeam_channel::unbounded;
-use ignore::{types::TypesBuilder, WalkBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaValue},
+    FromLua, IntoLua,
+};
 use nucleo::Utf32String;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
 use tokio::{runtime::Runtime, task::JoinHandle};
 
-use crate::picker::Entry;
+use crate::error::{Error, Result};
+use crate::index_cache::{CachedDirectory, CachedEntry, IndexCache};
+use crate::picker::{Entry, EntryKind, FileMetadata};
 
 pub struct Injector<T: Entry>(nucleo::Injector<T>);
 
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Source {
+    #[default]
+    Files,
+    Directories,
+}
+
+impl FromLua<'_> for Source {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                Source::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid source: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Sources must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for Source {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+/// A directory walked into the picker, labelled so matches can show where they came from
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Root {
+    pub path: String,
+    pub label: String,
+}
+
+impl Root {
+    /// Labels the root with its directory name
+    pub fn new(path: String) -> Self {
+        let label = Path::new(&path)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.clone());
+
+        Self { path, label }
+    }
+}
+
+impl FromLua<'_> for Root {
+    fn from_lua(value: LuaValue<'_>, lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::Table(table) => {
+                let root = Root::new(table.get("path")?);
+                match table.get::<_, Option<String>>("label")? {
+                    Some(label) => Ok(Root { label, ..root }),
+                    None => Ok(root),
+                }
+            }
+            value => Ok(Root::new(String::from_lua(value, lua)?)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WalkOptions {
+    pub source: Source,
+    pub git_ignore: bool,
+    /// Collect `FileMetadata` for every entry
+    pub metadata: bool,
+    /// How many directories deep to walk, unlimited when `None`
+    pub max_depth: Option<usize>,
+    /// Skip archives and compressed files
+    pub exclude_compressed: bool,
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        Self {
+            source: Source::default(),
+            git_ignore: true,
+            metadata: false,
+            max_depth: None,
+            exclude_compressed: true,
+        }
+    }
+}
+
 impl<T: Entry> From<nucleo::Injector<T>> for Injector<T> {
     fn from(value: nucleo::Injector<T>) -> Self {
         Self(value)
@@ -26,58 +125,373 @@ impl<T: Entry> Injector<T> {
         self.0.push(value, fill_columns)
     }
 
-    pub fn populate_files_sorted(self, cwd: String, git_ignore: bool) {
-        log::info!("Populating picker with {}", &cwd);
-        let runtime = Runtime::new().expect("Failed to create runtime");
+    /// Walks `cwd` into the matcher on a background thread, tagging every entry with `label`
+    /// when one is given. Returns right away, join the handle to wait for the walk to finish.
+    /// Stops early once `cancelled` is set.
+    pub fn populate_files_sorted(
+        self,
+        cwd: String,
+        label: Option<String>,
+        options: WalkOptions,
+        cancelled: Arc<AtomicBool>,
+    ) -> thread::JoinHandle<Result<()>> {
+        thread::spawn(move || {
+            log::info!("Populating picker with {}", &cwd);
+            self.walk(cwd, label, options, cancelled, None, false)?;
+            log::info!("Finished populating picker");
+
+            Ok(())
+        })
+    }
+
+    /// Pushes the entries cached for `cwd` in `cache_dir` right away, then walks `cwd` again
+    /// to refresh the cache, pushing whatever was added since. Returns whether cached entries
+    /// turned out to be deleted, in which case the matcher holds them until it is reloaded
+    /// from the refreshed cache.
+    pub fn populate_files_cached(
+        self,
+        cwd: String,
+        label: Option<String>,
+        options: WalkOptions,
+        cancelled: Arc<AtomicBool>,
+        cache_dir: PathBuf,
+    ) -> Result<bool> {
+        let key = IndexCache::key(&cwd, &options);
+        let cached = IndexCache::load(&cache_dir, &key);
 
-        let (tx, rx) = unbounded::<T>();
-        let _add_to_injector_thread: JoinHandle<Result<(), _>> = runtime.spawn(async move {
-            for val in rx.iter() {
-                self.push(val.clone(), |dst| dst[0] = val.into_utf32());
+        if let Some(cache) = &cached {
+            log::info!("Populating picker with {} from the index cache", &cwd);
+            for entry in cache.entries.iter().cloned() {
+                if cancelled.load(Ordering::Relaxed) {
+                    return Ok(false);
+                }
+                self.push_cached(entry, &cwd, label.as_ref(), options);
             }
-            anyhow::Ok(())
+
+            if cache.is_fresh() {
+                return Ok(false);
+            }
+            log::info!("Index cache for {} is stale, walking it again", &cwd);
+        }
+
+        let known = cached.as_ref().map(|cache| {
+            cache
+                .entries
+                .iter()
+                .map(|entry| entry.path.clone())
+                .collect::<HashSet<_>>()
         });
+        let walked = match self.walk(cwd, label, options, cancelled, known, true)? {
+            Some(walked) => walked,
+            None => return Ok(false),
+        };
+        let deleted = cached.is_some_and(|cache| {
+            let walked_paths = walked
+                .entries
+                .iter()
+                .map(|entry| entry.path.as_slice())
+                .collect::<HashSet<_>>();
+            cache
+                .entries
+                .iter()
+                .any(|entry| !walked_paths.contains(entry.path.as_slice()))
+        });
+        IndexCache::new(key, walked.directories, walked.entries).save(&cache_dir)?;
 
-        runtime.spawn(async move {
-            let dir = Path::new(&cwd);
-            log::info!("Spawning sorted file searcher...");
-            let mut walk_builder = WalkBuilder::new(dir);
-            walk_builder
-                .hidden(false)
-                .follow_links(true)
-                .git_ignore(git_ignore)
-                .ignore(true)
-                .sort_by_file_name(std::cmp::Ord::cmp);
-
-            let mut type_builder = TypesBuilder::new();
-            type_builder
-                .add(
-                    "compressed",
-                    "*.{zip,gz,bz2,zst,lzo,sz,tgz,tbz2,lz,lz4,lzma,lzo,z,Z,xz,7z,rar,cab}",
-                )
-                .expect("Invalid type definition");
-            type_builder.negate("all");
-            let excluded_types = type_builder
-                .build()
-                .expect("failed to build excluded_types");
-            walk_builder.types(excluded_types);
-            let tx = tx.clone();
-            for path in walk_builder.build() {
-                let cwd = cwd.clone();
-                match path {
-                    Ok(file) if file.path().is_file() => {
-                        if tx
-                            .send(Entry::from_path(file.path(), Some(cwd.clone())))
-                            .is_ok()
-                        {
-                            // log::info!("Sending {:?}", file.path());
+        Ok(deleted)
+    }
+
+    /// Pushes the entries cached for `cwd` without walking it
+    pub fn populate_from_cache(
+        self,
+        cwd: String,
+        label: Option<String>,
+        options: WalkOptions,
+        cancelled: Arc<AtomicBool>,
+        cache_dir: PathBuf,
+    ) {
+        let key = IndexCache::key(&cwd, &options);
+        let Some(cache) = IndexCache::load(&cache_dir, &key) else {
+            return;
+        };
+
+        for entry in cache.entries {
+            if cancelled.load(Ordering::Relaxed) {
+                break;
+            }
+            self.push_cached(entry, &cwd, label.as_ref(), options);
+        }
+    }
+
+    /// Pushes a cached entry with its metadata read fresh
+    fn push_cached(
+        &self,
+        mut entry: CachedEntry,
+        cwd: &str,
+        label: Option<&String>,
+        options: WalkOptions,
+    ) {
+        if options.metadata {
+            entry.read_metadata();
+        }
+        match entry.into_entry::<T>(cwd, label) {
+            Ok(entry) => {
+                self.push(entry.clone(), |dst| dst[0] = entry.into_utf32());
+            }
+            Err(err) => log::warn!("Skipping {}", err),
+        }
+    }
+
+    /// Pushes what the walk finds into the matcher, except for the `known` paths that already
+    /// were, and collects it for the index cache when `record` is set. Returns `None` when the
+    /// walk was cancelled.
+    fn walk(
+        self,
+        cwd: String,
+        label: Option<String>,
+        options: WalkOptions,
+        cancelled: Arc<AtomicBool>,
+        known: Option<HashSet<Vec<u8>>>,
+        record: bool,
+    ) -> Result<Option<Walked>> {
+        let runtime = Runtime::new().map_err(Error::Runtime)?;
+        let excluded_types = options
+            .exclude_compressed
+            .then(excluded_types)
+            .transpose()?;
+
+        let (tx, rx) = unbounded::<CachedEntry>();
+        let injector_cancelled = cancelled.clone();
+        let injector_cwd = cwd.clone();
+        let add_to_injector_thread: JoinHandle<Vec<CachedEntry>> =
+            runtime.spawn_blocking(move || {
+                let mut entries = Vec::new();
+                for val in rx.iter() {
+                    if injector_cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if !known
+                        .as_ref()
+                        .is_some_and(|known| known.contains(&val.path))
+                    {
+                        match val.clone().into_entry::<T>(&injector_cwd, label.as_ref()) {
+                            Ok(entry) => {
+                                self.push(entry.clone(), |dst| dst[0] = entry.into_utf32());
+                            }
+                            Err(err) => log::warn!("Skipping {}", err),
                         }
                     }
-                    _ => (),
+                    if record {
+                        entries.push(val);
+                    }
+                }
+                entries
+            });
+
+        let searcher_cancelled = cancelled.clone();
+        let file_searcher_thread: JoinHandle<Vec<CachedDirectory>> =
+            runtime.spawn_blocking(move || {
+                let dir = Path::new(&cwd);
+                log::info!("Spawning sorted file searcher...");
+                let mut walk_builder = WalkBuilder::new(dir);
+                walk_builder
+                    .hidden(false)
+                    .follow_links(true)
+                    .git_ignore(options.git_ignore)
+                    .ignore(true)
+                    .max_depth(options.max_depth)
+                    .sort_by_file_name(std::cmp::Ord::cmp)
+                    .filter_entry(|entry| entry.file_name() != ".git");
+                if let Some(excluded_types) = excluded_types {
+                    walk_builder.types(excluded_types);
+                }
+                let mut directories = Vec::new();
+                for path in walk_builder.build() {
+                    if searcher_cancelled.load(Ordering::Relaxed) {
+                        log::info!("Cancelled walking {}", cwd);
+                        break;
+                    }
+                    let file = match path {
+                        Ok(file) => file,
+                        Err(_) => continue,
+                    };
+                    let is_dir = file.path().is_dir();
+                    if record && is_dir {
+                        directories.push(CachedDirectory::new(file.path()));
+                    }
+                    let kind = match options.source {
+                        Source::Files if file.path().is_file() => EntryKind::File,
+                        Source::Directories if file.depth() > 0 && is_dir => EntryKind::Directory,
+                        _ => continue,
+                    };
+                    let _ = tx.send(CachedEntry {
+                        path: crate::path::to_bytes(file.path()),
+                        kind,
+                        metadata: options
+                            .metadata
+                            .then(|| FileMetadata::from_dir_entry(&file))
+                            .flatten(),
+                    });
                 }
+                directories
+            });
+
+        log::info!("After spawning file searcher...");
+
+        // Both tasks block, so keep the runtime alive until the walk has been fully injected.
+        let (directories, entries) =
+            runtime.block_on(async { tokio::join!(file_searcher_thread, add_to_injector_thread) });
+
+        if cancelled.load(Ordering::Relaxed) {
+            return Ok(None);
+        }
+
+        Ok(Some(Walked {
+            directories: directories.unwrap_or_default(),
+            entries: entries.unwrap_or_default(),
+        }))
+    }
+}
+
+/// Everything a walk found, as stored in the index cache
+struct Walked {
+    directories: Vec<CachedDirectory>,
+    entries: Vec<CachedEntry>,
+}
+
+fn excluded_types() -> Result<Types> {
+    let mut type_builder = TypesBuilder::new();
+    type_builder.add(
+        "compressed",
+        "*.{zip,gz,bz2,zst,lzo,sz,tgz,tbz2,lz,lz4,lzma,lzo,z,Z,xz,7z,rar,cab}",
+    )?;
+    type_builder.negate("all");
+
+    Ok(type_builder.build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::picker::{FileEntry, Picker, SortDirection};
+
+    struct Fixture {
+        root: tempfile::TempDir,
+        cache_dir: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        fn new(files: &[&str]) -> Self {
+            let root = tempfile::tempdir().unwrap();
+            for file in files {
+                fs::write(root.path().join(file), "").unwrap();
+            }
+
+            Self {
+                root,
+                cache_dir: tempfile::tempdir().unwrap(),
             }
+        }
+
+        fn cwd(&self) -> String {
+            self.root.path().to_string_lossy().to_string()
+        }
+
+        fn key(&self) -> String {
+            IndexCache::key(&self.cwd(), &WalkOptions::default())
+        }
+
+        /// Populates a new picker from the cache, returning whether it was stale and the names
+        /// of the pushed entries
+        fn populate(&self) -> (bool, Vec<String>) {
+            let mut picker = Picker::<FileEntry>::new(self.cwd(), SortDirection::Descending);
+            let stale = picker
+                .matcher
+                .injector()
+                .populate_files_cached(
+                    self.cwd(),
+                    None,
+                    WalkOptions::default(),
+                    Arc::default(),
+                    self.cache_dir.path().to_path_buf(),
+                )
+                .unwrap();
+            while picker.matcher.tick(10).0.running {}
+
+            let mut names = picker
+                .matcher
+                .snapshot()
+                .matched_items(..)
+                .map(|item| {
+                    Path::new(item.data.path())
+                        .file_name()
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            names.sort();
+
+            (stale, names)
+        }
+    }
+
+    #[test]
+    fn walks_and_caches_without_a_cache() {
+        let fixture = Fixture::new(&["a", "b"]);
+
+        assert_eq!(fixture.populate(), (false, vec!["a".into(), "b".into()]));
+        let cache = IndexCache::load(fixture.cache_dir.path(), &fixture.key()).unwrap();
+        assert_eq!(cache.entries.len(), 2);
+    }
+
+    #[test]
+    fn trusts_a_fresh_cache_without_walking() {
+        let fixture = Fixture::new(&["a", "b"]);
+        fixture.populate();
+
+        // An entry only the cache knows about survives, which it wouldn't if the root was walked
+        let mut cache = IndexCache::load(fixture.cache_dir.path(), &fixture.key()).unwrap();
+        cache.entries.push(CachedEntry {
+            path: crate::path::to_bytes(&fixture.root.path().join("ghost")),
+            kind: EntryKind::File,
+            metadata: None,
         });
+        IndexCache::new(fixture.key(), cache.directories, cache.entries)
+            .save(fixture.cache_dir.path())
+            .unwrap();
 
-        log::info!("After spawning file searcher...");
+        assert_eq!(
+            fixture.populate(),
+            (false, vec!["a".into(), "b".into(), "ghost".into()])
+        );
+    }
+
+    #[test]
+    fn pushes_added_files_once() {
+        let fixture = Fixture::new(&["a", "b"]);
+        fixture.populate();
+        fs::write(fixture.root.path().join("c"), "").unwrap();
+
+        assert_eq!(
+            fixture.populate(),
+            (false, vec!["a".into(), "b".into(), "c".into()])
+        );
+        let cache = IndexCache::load(fixture.cache_dir.path(), &fixture.key()).unwrap();
+        assert_eq!(cache.entries.len(), 3);
+    }
+
+    #[test]
+    fn reports_deleted_files_as_stale() {
+        let fixture = Fixture::new(&["a", "b"]);
+        fixture.populate();
+        fs::remove_file(fixture.root.path().join("a")).unwrap();
+
+        // The deleted entry stays pushed until the picker reloads from the refreshed cache
+        assert_eq!(fixture.populate(), (true, vec!["a".into(), "b".into()]));
+        let cache = IndexCache::load(fixture.cache_dir.path(), &fixture.key()).unwrap();
+        assert_eq!(cache.entries.len(), 1);
     }
 }
@@ -1,47 +1,73 @@
 use std::env::current_dir;
-use std::fs::File;
 
-use log::LevelFilter;
+use error::Error;
 use mlua::prelude::*;
-
 use picker::{FileEntry, Picker};
-use simplelog::{Config, WriteLogger};
 
-mod buffer;
-mod injector;
-mod picker;
-mod previewer;
+pub mod archive;
+pub mod bounded_cache;
+pub mod buffer;
+pub mod encoding;
+pub mod error;
+pub mod format;
+pub mod frecency;
+pub mod git;
+pub mod index_cache;
+pub mod injector;
+pub mod line_index;
+pub mod logger;
+pub mod media;
+pub mod ordering;
+pub mod path;
+pub mod picker;
+pub mod previewer;
+pub mod query;
+pub mod quickfix;
+pub mod syntax;
+pub mod tiebreak;
 
 pub fn init_picker(_: &Lua, params: (Option<picker::Config>,)) -> LuaResult<Picker<FileEntry>> {
-    let config = match params.0 {
-        Some(config) => config,
-        None => picker::Config::default(),
-    };
+    let config = params.0.unwrap_or_default();
 
     let cwd = match config.cwd {
         Some(cwd) => cwd,
-        None => current_dir().unwrap().to_string_lossy().to_string(),
+        None => current_dir()
+            .map_err(Error::CurrentDir)?
+            .to_string_lossy()
+            .to_string(),
     };
     let sort_direction = config.sort_direction.unwrap_or_default();
 
     let mut picker = Picker::new(cwd, sort_direction);
-
-    picker.populate_files();
+    let reindexed = picker.update_config(picker::Config {
+        cwd: None,
+        ..config
+    });
+    if !reindexed {
+        picker.populate_files();
+    }
 
     Ok(picker)
 }
 
 #[mlua::lua_module]
-fn nucleo_rs(lua: &Lua) -> LuaResult<LuaTable> {
-    let _ = WriteLogger::init(
-        LevelFilter::Info,
-        Config::default(),
-        File::create("nucleo.log").unwrap(),
-    );
+fn nucleo_rs(lua: &Lua) -> LuaResult<LuaTable<'_>> {
+    logger::init(lua)?;
     log::info!("Initialized logger");
 
     let exports = lua.create_table()?;
 
+    exports.set(
+        "setup_logging",
+        lua.create_function(|lua, config: Option<logger::LogConfig>| {
+            logger::setup(lua, config.unwrap_or_default())
+        })?,
+    )?;
+    exports.set(
+        "drain_logs",
+        lua.create_function(|lua, ()| logger::drain(lua))?,
+    )?;
+
     exports.set("Picker", lua.create_function(init_picker)?)?;
     exports.set(
         "Previewer",
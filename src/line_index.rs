@@ -0,0 +1,291 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+
+use crate::bounded_cache::BoundedCache;
+
+/// Files larger than this are previewed by reading only the requested lines
+pub const STREAM_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Lines between two recorded offsets, a lookup scans at most this many lines past one
+const CHECKPOINT_STRIDE: usize = 256;
+
+/// Bytes read from the file at a time while scanning
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Indexes kept around before the oldest are dropped
+const MAX_CACHED_INDEXES: usize = 32;
+
+/// Byte offsets of every `CHECKPOINT_STRIDE`th line, extended only as far as previews need
+#[derive(Debug)]
+struct LineIndex {
+    len: u64,
+    mtime: Option<SystemTime>,
+    /// The offset of line `i * CHECKPOINT_STRIDE` at index `i`
+    checkpoints: Vec<u64>,
+    scanned_bytes: u64,
+    /// Newlines found in the first `scanned_bytes`
+    scanned_lines: usize,
+}
+
+impl LineIndex {
+    fn new(len: u64, mtime: Option<SystemTime>) -> Self {
+        Self {
+            len,
+            mtime,
+            checkpoints: vec![0],
+            scanned_bytes: 0,
+            scanned_lines: 0,
+        }
+    }
+
+    /// Starts over when the file's size or mtime changed since it was indexed
+    fn refresh(&mut self, file: &File) -> io::Result<()> {
+        let metadata = file.metadata()?;
+        let (len, mtime) = (metadata.len(), metadata.modified().ok());
+        if self.len != len || self.mtime != mtime {
+            *self = Self::new(len, mtime);
+        }
+
+        Ok(())
+    }
+
+    fn is_complete(&self) -> bool {
+        self.scanned_bytes >= self.len
+    }
+
+    /// Scans forward until `line` newlines have been seen or the end of the file
+    fn scan_to(&mut self, file: &mut File, line: usize) -> io::Result<()> {
+        if self.scanned_lines >= line || self.is_complete() {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(self.scanned_bytes))?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        while self.scanned_lines < line && !self.is_complete() {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                // The file shrank since it was indexed
+                self.len = self.scanned_bytes;
+                break;
+            }
+
+            let mut consumed = read;
+            for pos in memchr::memchr_iter(b'\n', &buf[..read]) {
+                self.scanned_lines += 1;
+                if self.scanned_lines.is_multiple_of(CHECKPOINT_STRIDE) {
+                    self.checkpoints.push(self.scanned_bytes + pos as u64 + 1);
+                }
+                if self.scanned_lines == line {
+                    consumed = pos + 1;
+                    break;
+                }
+            }
+            self.scanned_bytes += consumed as u64;
+        }
+
+        Ok(())
+    }
+
+    /// The number of lines, or `limit` if the file has more than that.
+    /// A trailing newline doesn't start another line.
+    fn count_lines_up_to(&mut self, file: &mut File, limit: usize) -> io::Result<usize> {
+        self.scan_to(file, limit)?;
+        if !self.is_complete() {
+            return Ok(limit);
+        }
+
+        let has_partial_line = match self.len {
+            0 => false,
+            len => {
+                let mut last = [0; 1];
+                file.seek(SeekFrom::Start(len - 1))?;
+                file.read_exact(&mut last)?;
+                last[0] != b'\n'
+            }
+        };
+
+        Ok(limit.min(self.scanned_lines + usize::from(has_partial_line)))
+    }
+
+    /// The bytes of lines `start_line..end_line`, including their newlines
+    fn read_lines(
+        &mut self,
+        file: &mut File,
+        start_line: usize,
+        end_line: usize,
+    ) -> io::Result<Vec<u8>> {
+        self.scan_to(file, start_line)?;
+        let checkpoint = (start_line / CHECKPOINT_STRIDE).min(self.checkpoints.len() - 1);
+        let mut skip = start_line - checkpoint * CHECKPOINT_STRIDE;
+        let mut remaining = end_line.saturating_sub(start_line);
+
+        file.seek(SeekFrom::Start(self.checkpoints[checkpoint]))?;
+        let mut buf = vec![0; CHUNK_SIZE];
+        let mut bytes = Vec::new();
+        while remaining > 0 {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+
+            let mut chunk = &buf[..read];
+            while skip > 0 {
+                match memchr::memchr(b'\n', chunk) {
+                    Some(pos) => {
+                        chunk = &chunk[pos + 1..];
+                        skip -= 1;
+                    }
+                    None => {
+                        chunk = &[];
+                        break;
+                    }
+                }
+            }
+            while remaining > 0 && !chunk.is_empty() {
+                match memchr::memchr(b'\n', chunk) {
+                    Some(pos) => {
+                        bytes.extend_from_slice(&chunk[..=pos]);
+                        chunk = &chunk[pos + 1..];
+                        remaining -= 1;
+                    }
+                    None => {
+                        bytes.extend_from_slice(chunk);
+                        chunk = &[];
+                    }
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+type LineIndexes = BoundedCache<PathBuf, Arc<Mutex<LineIndex>>, MAX_CACHED_INDEXES>;
+
+static LINE_INDEXES: Lazy<Mutex<LineIndexes>> = Lazy::new(Mutex::default);
+
+/// The index of `path`, which callers refresh after locking it.
+/// Each index has its own lock so that scanning one file doesn't hold up previews of others.
+fn line_index(path: &Path) -> Arc<Mutex<LineIndex>> {
+    LINE_INDEXES
+        .lock()
+        .get_or_insert_with(path.to_path_buf(), || {
+            Arc::new(Mutex::new(LineIndex::new(0, None)))
+        })
+        .0
+}
+
+/// Whether `path` is big enough that previews should avoid reading all of it
+pub fn is_huge(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.len() > STREAM_THRESHOLD)
+}
+
+/// The raw bytes of lines `start_line..end_line`, reading no further into the file than needed
+pub fn read_lines(path: &Path, start_line: usize, end_line: usize) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let index = line_index(path);
+    let mut index = index.lock();
+    index.refresh(&file)?;
+
+    index.read_lines(&mut file, start_line, end_line)
+}
+
+/// The number of lines in `path`, scanning only as far as `limit` lines
+pub fn count_lines_up_to(path: &Path, limit: usize) -> io::Result<usize> {
+    let mut file = File::open(path)?;
+    let index = line_index(path);
+    let mut index = index.lock();
+    index.refresh(&file)?;
+
+    index.count_lines_up_to(&mut file, limit)
+}
+
+/// The first bytes of `path`, enough to tell its encoding
+pub fn read_head(path: &Path, len: usize) -> io::Result<Vec<u8>> {
+    let mut head = Vec::with_capacity(len);
+    File::open(path)?.take(len as u64).read_to_end(&mut head)?;
+
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn numbered_lines(count: usize) -> String {
+        (0..count).map(|line| format!("line {}\n", line)).collect()
+    }
+
+    fn file_with(contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+        file
+    }
+
+    fn lines(path: &Path, start_line: usize, end_line: usize) -> String {
+        String::from_utf8(read_lines(path, start_line, end_line).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn reads_ranges_crossing_checkpoints() {
+        let contents = numbered_lines(1000);
+        let file = file_with(&contents);
+        let expected = |start: usize, end: usize| -> String {
+            contents
+                .lines()
+                .skip(start)
+                .take(end - start)
+                .map(|line| format!("{}\n", line))
+                .collect()
+        };
+
+        assert_eq!(lines(file.path(), 250, 260), expected(250, 260));
+        // Scanning far ahead records later checkpoints, earlier ranges start from those before them
+        assert_eq!(lines(file.path(), 900, 905), expected(900, 905));
+        assert_eq!(lines(file.path(), 510, 770), expected(510, 770));
+        assert_eq!(lines(file.path(), 0, 3), expected(0, 3));
+    }
+
+    #[test]
+    fn reads_ranges_past_the_end() {
+        let file = file_with(&numbered_lines(300));
+
+        assert_eq!(lines(file.path(), 298, 310), "line 298\nline 299\n");
+        assert_eq!(lines(file.path(), 300, 310), "");
+        assert_eq!(lines(file.path(), 1000, 1010), "");
+    }
+
+    #[test]
+    fn counts_lines_up_to_the_limit() {
+        let file = file_with(&numbered_lines(600));
+
+        assert_eq!(count_lines_up_to(file.path(), 10).unwrap(), 10);
+        assert_eq!(count_lines_up_to(file.path(), 600).unwrap(), 600);
+        assert_eq!(count_lines_up_to(file.path(), usize::MAX).unwrap(), 600);
+    }
+
+    #[test]
+    fn handles_a_missing_trailing_newline() {
+        let file = file_with("a\nb\nc");
+
+        assert_eq!(count_lines_up_to(file.path(), usize::MAX).unwrap(), 3);
+        assert_eq!(lines(file.path(), 2, 3), "c");
+        assert_eq!(lines(file.path(), 1, 10), "b\nc");
+    }
+
+    #[test]
+    fn counts_empty_files() {
+        let file = file_with("");
+
+        assert_eq!(count_lines_up_to(file.path(), usize::MAX).unwrap(), 0);
+        assert_eq!(lines(file.path(), 0, 10), "");
+    }
+}
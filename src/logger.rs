@@ -0,0 +1,280 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use log::{LevelFilter, Log, Metadata, Record};
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaTable, LuaValue},
+    FromLua, IntoLua, LuaSerdeExt,
+};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use simplelog::WriteLogger;
+use strum::{Display, EnumString};
+
+use crate::error::Error;
+
+const LOG_FILE_NAME: &str = "nucleo.log";
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Warn;
+/// Records kept for Lua when nothing drains them, oldest are dropped first
+const MAX_QUEUED_RECORDS: usize = 1000;
+
+static LOGGER: Lazy<Logger> = Lazy::new(Logger::default);
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum LogTarget {
+    #[default]
+    File,
+    Lua,
+    Off,
+}
+
+impl FromLua<'_> for LogTarget {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                LogTarget::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid log target: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Log targets must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for LogTarget {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, Default)]
+pub struct LogConfig {
+    pub target: Option<LogTarget>,
+    /// Defaults to `nucleo.log` inside Neovim's log directory
+    pub path: Option<String>,
+    pub level: Option<String>,
+    /// Rotate the log file once it grows past this many bytes
+    pub max_size: Option<u64>,
+    /// Number of rotated files to keep around
+    pub max_files: Option<usize>,
+}
+
+impl FromLua<'_> for LogConfig {
+    fn from_lua(value: LuaValue<'_>, lua: &'_ Lua) -> LuaResult<Self> {
+        let table = LuaTable::from_lua(value, lua)?;
+        Ok(LogConfig {
+            target: table.get("target")?,
+            path: table.get("path")?,
+            level: table.get("level")?,
+            max_size: table.get("max_size")?,
+            max_files: table.get("max_files")?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogRecord {
+    pub level: String,
+    pub target: String,
+    pub message: String,
+}
+
+impl From<&Record<'_>> for LogRecord {
+    fn from(record: &Record<'_>) -> Self {
+        Self {
+            level: record.level().as_str().to_lowercase(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+        }
+    }
+}
+
+enum Sink {
+    Off,
+    File(Box<WriteLogger<RotatingFile>>),
+    Lua(VecDeque<LogRecord>),
+}
+
+struct Logger {
+    sink: Mutex<Sink>,
+    /// Problems setting up the logger itself, handed to Lua since they can't be logged
+    warnings: Mutex<Vec<LogRecord>>,
+}
+
+impl Default for Logger {
+    fn default() -> Self {
+        Self {
+            sink: Mutex::new(Sink::Off),
+            warnings: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+
+        match &mut *self.sink.lock() {
+            Sink::Off => {}
+            Sink::File(logger) => logger.log(record),
+            Sink::Lua(queue) => {
+                if queue.len() >= MAX_QUEUED_RECORDS {
+                    queue.pop_front();
+                }
+                queue.push_back(record.into());
+            }
+        }
+    }
+
+    fn flush(&self) {
+        if let Sink::File(logger) = &*self.sink.lock() {
+            logger.flush();
+        }
+    }
+}
+
+/// A log file that moves itself to `<path>.1`, `<path>.2`, ... once it exceeds `max_size`
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_size: Option<u64>,
+    max_files: usize,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_size: Option<u64>, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            path,
+            file,
+            size,
+            max_size,
+            max_files,
+        })
+    }
+
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.max_files > 0 {
+            for index in (1..self.max_files).rev() {
+                let from = self.rotated_path(index);
+                if from.exists() {
+                    fs::rename(from, self.rotated_path(index + 1))?;
+                }
+            }
+            fs::rename(&self.path, self.rotated_path(1))?;
+        }
+        self.file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&self.path)?;
+        self.size = 0;
+
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.max_size.is_some_and(|max_size| self.size >= max_size) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+/// Installs the global logger with the default configuration.
+/// Safe to call more than once, later calls leave the existing logger in place.
+/// Logging is turned off rather than failing to load the module when the default log file
+/// can't be opened, the reason is queued for `drain`.
+pub fn init(lua: &Lua) -> LuaResult<()> {
+    if log::set_logger(&*LOGGER).is_ok() {
+        if let Err(err) = setup(lua, LogConfig::default()) {
+            *LOGGER.sink.lock() = Sink::Off;
+            log::set_max_level(LevelFilter::Off);
+            LOGGER.warnings.lock().push(LogRecord {
+                level: "warn".to_string(),
+                target: module_path!().to_string(),
+                message: format!("Logging is disabled: {}", err),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+pub fn setup(lua: &Lua, config: LogConfig) -> LuaResult<()> {
+    let level = match config.level {
+        Some(level) => LevelFilter::from_str(&level).map_err(|_| Error::InvalidLogLevel(level))?,
+        None => DEFAULT_LEVEL,
+    };
+
+    let target = config.target.unwrap_or_default();
+    let sink = match target {
+        LogTarget::Off => Sink::Off,
+        LogTarget::Lua => Sink::Lua(VecDeque::new()),
+        LogTarget::File => {
+            let path = match config.path {
+                Some(path) => PathBuf::from(path),
+                None => crate::path::stdpath(lua, "log").join(LOG_FILE_NAME),
+            };
+            let file =
+                RotatingFile::open(path.clone(), config.max_size, config.max_files.unwrap_or(1))
+                    .map_err(|source| Error::LogFile {
+                        path: path.to_string_lossy().to_string(),
+                        source,
+                    })?;
+            Sink::File(WriteLogger::new(level, simplelog::Config::default(), file))
+        }
+    };
+
+    *LOGGER.sink.lock() = sink;
+    log::set_max_level(match target {
+        LogTarget::Off => LevelFilter::Off,
+        _ => level,
+    });
+
+    Ok(())
+}
+
+/// Takes the warnings about setting up the logger, along with the records queued for Lua when
+/// logging with the `lua` target
+pub fn drain(lua: &Lua) -> LuaResult<LuaValue<'_>> {
+    let mut records = std::mem::take(&mut *LOGGER.warnings.lock());
+    if let Sink::Lua(queue) = &mut *LOGGER.sink.lock() {
+        records.extend(queue.drain(..));
+    }
+
+    lua.to_value(&records)
+}
@@ -0,0 +1,841 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::format::{format_size, format_timestamp};
+
+/// Bytes read up front, enough for the headers of every format that keeps them at the start
+const HEAD_LENGTH: usize = 64 * 1024;
+
+/// The largest `moov` box read into memory when looking for the duration and dimensions
+const MAX_MOVIE_HEADER_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// Seconds between the QuickTime epoch in 1904 and the Unix epoch
+const QUICKTIME_EPOCH_OFFSET: u64 = 2_082_844_800;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaKind {
+    Png,
+    Jpeg,
+    Gif,
+    Bmp,
+    Webp,
+    Wav,
+    Flac,
+    Mp3,
+    Mp4,
+}
+
+impl MediaKind {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_string_lossy().to_lowercase();
+        match ext.as_str() {
+            "png" => Some(MediaKind::Png),
+            "jpg" | "jpeg" => Some(MediaKind::Jpeg),
+            "gif" => Some(MediaKind::Gif),
+            "bmp" => Some(MediaKind::Bmp),
+            "webp" => Some(MediaKind::Webp),
+            "wav" => Some(MediaKind::Wav),
+            "flac" => Some(MediaKind::Flac),
+            "mp3" => Some(MediaKind::Mp3),
+            "mp4" | "m4v" | "m4a" | "mov" => Some(MediaKind::Mp4),
+            _ => None,
+        }
+    }
+}
+
+/// What could be read from the headers of an image, video or audio file
+#[derive(Debug, Default)]
+struct MediaInfo {
+    format: String,
+    dimensions: Option<(u32, u32)>,
+    duration: Option<f64>,
+    /// Formatted as `YYYY-MM-DD HH:MM`
+    created: Option<String>,
+    details: Vec<(&'static str, String)>,
+}
+
+impl MediaInfo {
+    fn new(format: impl Into<String>) -> Self {
+        Self {
+            format: format.into(),
+            ..Default::default()
+        }
+    }
+
+    fn render(self, size: u64) -> String {
+        let mut fields = vec![("Format", self.format)];
+        if let Some((width, height)) = self.dimensions {
+            fields.push(("Dimensions", format!("{} × {}", width, height)));
+        }
+        if let Some(duration) = self.duration {
+            fields.push(("Duration", format_duration(duration)));
+        }
+        fields.extend(self.details);
+        if let Some(created) = self.created {
+            fields.push(("Created", created));
+        }
+        fields.push(("Size", format_size(size)));
+
+        fields
+            .iter()
+            .map(|(label, value)| format!("{:<12}{}", label, value))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn format_duration(secs: f64) -> String {
+    let total = secs.round() as u64;
+    match total / 3600 {
+        0 => format!("{}:{:02}", total / 60, total % 60),
+        hours => format!("{}:{:02}:{:02}", hours, (total % 3600) / 60, total % 60),
+    }
+}
+
+fn be_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn le_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn be_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn le_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn be_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_be_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+fn le_u24(bytes: &[u8], at: usize) -> Option<u32> {
+    let bytes = bytes.get(at..at + 3)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]))
+}
+
+fn png_info(head: &[u8]) -> Option<MediaInfo> {
+    if !head.starts_with(b"\x89PNG\r\n\x1a\n") || head.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let color = match head.get(25)? {
+        0 => "Grayscale",
+        2 => "RGB",
+        3 => "Indexed",
+        4 => "Grayscale + alpha",
+        6 => "RGBA",
+        _ => "Unknown",
+    };
+
+    let mut info = MediaInfo::new("PNG image");
+    info.dimensions = Some((be_u32(head, 16)?, be_u32(head, 20)?));
+    info.details
+        .push(("Color", format!("{}, {}-bit", color, head.get(24)?)));
+    Some(info)
+}
+
+fn gif_info(head: &[u8]) -> Option<MediaInfo> {
+    if !head.starts_with(b"GIF87a") && !head.starts_with(b"GIF89a") {
+        return None;
+    }
+
+    let mut info = MediaInfo::new("GIF image");
+    info.dimensions = Some((le_u16(head, 6)? as u32, le_u16(head, 8)? as u32));
+    Some(info)
+}
+
+fn bmp_info(head: &[u8]) -> Option<MediaInfo> {
+    if !head.starts_with(b"BM") {
+        return None;
+    }
+
+    let mut info = MediaInfo::new("BMP image");
+    let (dimensions, depth) = match le_u32(head, 14)? {
+        // The OS/2 header uses 16 bit dimensions
+        12 => (
+            (le_u16(head, 18)? as u32, le_u16(head, 20)? as u32),
+            le_u16(head, 24)?,
+        ),
+        _ => (
+            (
+                le_u32(head, 18)?,
+                // Negative heights store the rows top down
+                (le_u32(head, 22)? as i32).unsigned_abs(),
+            ),
+            le_u16(head, 28)?,
+        ),
+    };
+    info.dimensions = Some(dimensions);
+    info.details.push(("Color", format!("{}-bit", depth)));
+    Some(info)
+}
+
+fn webp_info(head: &[u8]) -> Option<MediaInfo> {
+    if !head.starts_with(b"RIFF") || head.get(8..12)? != b"WEBP" {
+        return None;
+    }
+
+    let (compression, dimensions) = match head.get(12..16)? {
+        b"VP8 " => (
+            "lossy",
+            (
+                (le_u16(head, 26)? & 0x3fff) as u32,
+                (le_u16(head, 28)? & 0x3fff) as u32,
+            ),
+        ),
+        b"VP8L" => {
+            let bits = le_u32(head, 21)?;
+            (
+                "lossless",
+                ((bits & 0x3fff) + 1, ((bits >> 14) & 0x3fff) + 1),
+            )
+        }
+        b"VP8X" => ("extended", (le_u24(head, 24)? + 1, le_u24(head, 27)? + 1)),
+        _ => return None,
+    };
+
+    let mut info = MediaInfo::new(format!("WebP image ({})", compression));
+    info.dimensions = Some(dimensions);
+    Some(info)
+}
+
+/// Converts an EXIF `YYYY:MM:DD HH:MM:SS` timestamp to `YYYY-MM-DD HH:MM`
+fn format_exif_date(value: &[u8]) -> Option<String> {
+    let value = std::str::from_utf8(value.get(..16)?).ok()?;
+    let (date, time) = value.split_once(' ')?;
+    let date = date.replace(':', "-");
+    (date.len() == 10 && !date.starts_with("0000")).then(|| format!("{} {}", date, time))
+}
+
+/// The date the photo was taken from a TIFF structured EXIF block, or when it was last edited
+fn exif_date(tiff: &[u8]) -> Option<String> {
+    let big_endian = match tiff.get(..2)? {
+        b"MM" => true,
+        b"II" => false,
+        _ => return None,
+    };
+    let u16_at = |at| match big_endian {
+        true => be_u16(tiff, at),
+        false => le_u16(tiff, at),
+    };
+    let u32_at = |at| match big_endian {
+        true => be_u32(tiff, at),
+        false => le_u32(tiff, at),
+    };
+    // Finds `tag` in the directory at `offset`, returning the value or offset field
+    let find_tag = |offset: usize, tag: u16| {
+        let count = u16_at(offset)? as usize;
+        (0..count)
+            .map(|idx| offset + 2 + idx * 12)
+            .find(|entry| u16_at(*entry) == Some(tag))
+            .and_then(|entry| Some((u32_at(entry + 4)? as usize, u32_at(entry + 8)? as usize)))
+    };
+    let ascii_value =
+        |(count, offset): (usize, usize)| format_exif_date(tiff.get(offset..offset + count)?);
+
+    let ifd0 = u32_at(4)? as usize;
+    let original = find_tag(ifd0, 0x8769)
+        .and_then(|(_, exif_ifd)| find_tag(exif_ifd, 0x9003))
+        .and_then(ascii_value);
+    original.or_else(|| find_tag(ifd0, 0x0132).and_then(ascii_value))
+}
+
+/// Skips `length` bytes of an untrusted size. Returns `false` when that is too far to seek.
+fn skip<R: Seek>(file: &mut R, length: u64) -> io::Result<bool> {
+    match i64::try_from(length) {
+        Ok(offset) => file.seek(SeekFrom::Current(offset)).map(|_| true),
+        Err(_) => Ok(false),
+    }
+}
+
+fn jpeg_info<R: Read + Seek>(file: &mut R) -> io::Result<Option<MediaInfo>> {
+    let mut marker = [0; 2];
+    file.read_exact(&mut marker)?;
+    if marker != [0xff, 0xd8] {
+        return Ok(None);
+    }
+
+    let mut info = MediaInfo::new("JPEG image");
+    loop {
+        let position = file.stream_position()?;
+        file.read_exact(&mut marker)?;
+        if marker[0] != 0xff {
+            return Ok(None);
+        }
+        // Markers may be padded with any number of 0xff bytes
+        while marker[1] == 0xff {
+            file.read_exact(&mut marker[1..])?;
+        }
+        // Standalone markers have no length
+        if (0xd0..=0xd7).contains(&marker[1]) || marker[1] == 0x01 {
+            continue;
+        }
+
+        let mut length = [0; 2];
+        file.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length).saturating_sub(2) as usize;
+        match marker[1] {
+            // Start of frame, except for the DHT, JPG and DAC markers sharing the range
+            0xc0..=0xcf if ![0xc4, 0xc8, 0xcc].contains(&marker[1]) => {
+                let mut frame = vec![0; length];
+                file.read_exact(&mut frame)?;
+                if let (Some(height), Some(width)) = (be_u16(&frame, 1), be_u16(&frame, 3)) {
+                    info.dimensions = Some((width as u32, height as u32));
+                }
+                if let (Some(precision), Some(components)) = (frame.first(), frame.get(5)) {
+                    let color = match components {
+                        1 => "Grayscale",
+                        3 => "YCbCr",
+                        4 => "CMYK",
+                        _ => "Unknown",
+                    };
+                    info.details
+                        .push(("Color", format!("{}, {}-bit", color, precision)));
+                }
+                // Everything of interest comes before the first frame
+                return Ok(Some(info));
+            }
+            0xe1 if info.created.is_none() => {
+                let mut app1 = vec![0; length];
+                file.read_exact(&mut app1)?;
+                if let Some(tiff) = app1.strip_prefix(b"Exif\0\0") {
+                    info.created = exif_date(tiff);
+                }
+            }
+            // The image data starts without a frame header having been seen
+            0xda | 0xd9 => return Ok(Some(info)),
+            _ => {
+                if !skip(file, length as u64)? {
+                    return Ok(None);
+                }
+            }
+        }
+        if file.stream_position()? <= position {
+            return Ok(None);
+        }
+    }
+}
+
+fn wav_info<R: Read + Seek>(file: &mut R) -> io::Result<Option<MediaInfo>> {
+    let mut header = [0; 12];
+    file.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WAVE" {
+        return Ok(None);
+    }
+
+    let mut info = MediaInfo::new("WAV audio");
+    let mut byte_rate = None;
+    let mut chunk = [0; 8];
+    loop {
+        let position = file.stream_position()?;
+        if file.read_exact(&mut chunk).is_err() {
+            break;
+        }
+        let size = le_u32(&chunk, 4).unwrap_or_default() as u64;
+        match &chunk[..4] {
+            b"fmt " => {
+                let mut format = vec![0; size.min(64) as usize];
+                file.read_exact(&mut format)?;
+                let (Some(channels), Some(sample_rate), Some(rate), Some(bits)) = (
+                    le_u16(&format, 2),
+                    le_u32(&format, 4),
+                    le_u32(&format, 8),
+                    le_u16(&format, 14),
+                ) else {
+                    return Ok(None);
+                };
+                byte_rate = Some(rate);
+                info.details.push((
+                    "Audio",
+                    format!("{} Hz, {} ch, {}-bit", sample_rate, channels, bits),
+                ));
+                if !skip(file, size - format.len() as u64 + (size & 1))? {
+                    return Ok(None);
+                }
+            }
+            b"data" => {
+                info.duration = byte_rate
+                    .filter(|rate| *rate > 0)
+                    .map(|rate| size as f64 / rate as f64);
+                break;
+            }
+            // Chunks are padded to an even length
+            _ => {
+                if !skip(file, size + (size & 1))? {
+                    return Ok(None);
+                }
+            }
+        }
+        if file.stream_position()? <= position {
+            return Ok(None);
+        }
+    }
+
+    Ok(Some(info))
+}
+
+fn flac_info(head: &[u8]) -> Option<MediaInfo> {
+    // STREAMINFO is always the first metadata block
+    if !head.starts_with(b"fLaC") || head.get(4)? & 0x7f != 0 {
+        return None;
+    }
+
+    let fields = be_u64(head, 18)?;
+    let sample_rate = fields >> 44;
+    let channels = ((fields >> 41) & 0x7) + 1;
+    let bits = ((fields >> 36) & 0x1f) + 1;
+    let samples = fields & 0xf_ffff_ffff;
+
+    let mut info = MediaInfo::new("FLAC audio");
+    if sample_rate > 0 && samples > 0 {
+        info.duration = Some(samples as f64 / sample_rate as f64);
+    }
+    info.details.push((
+        "Audio",
+        format!("{} Hz, {} ch, {}-bit", sample_rate, channels, bits),
+    ));
+    Some(info)
+}
+
+/// Bitrates in kb/s by bitrate index, for MPEG-1 layers I to III and MPEG-2 layers I and II/III
+const MP3_BITRATES: [[u32; 15]; 5] = [
+    [
+        0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+    ],
+    [
+        0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+    ],
+    [
+        0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+    ],
+    [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+];
+
+/// Reads the first frame header after the ID3 tag. The duration comes from the frame count of a
+/// Xing or Info header when the encoder wrote one, otherwise it assumes a constant bitrate.
+fn mp3_info(head: &[u8], file_size: u64) -> Option<MediaInfo> {
+    let tag_length = match head.starts_with(b"ID3") {
+        true => {
+            let size = head
+                .get(6..10)?
+                .iter()
+                .fold(0usize, |size, byte| (size << 7) | (*byte & 0x7f) as usize);
+            let footer = if head.get(5)? & 0x10 != 0 { 10 } else { 0 };
+            10 + size + footer
+        }
+        false => 0,
+    };
+    let frame_start = head
+        .get(tag_length..)?
+        .windows(2)
+        .position(|pair| pair[0] == 0xff && pair[1] & 0xe0 == 0xe0)?
+        + tag_length;
+    let frame = head.get(frame_start..frame_start + 4)?;
+
+    let (version, mpeg1) = match (frame[1] >> 3) & 0x3 {
+        3 => ("MPEG-1", true),
+        2 => ("MPEG-2", false),
+        0 => ("MPEG-2.5", false),
+        _ => return None,
+    };
+    let layer = match (frame[1] >> 1) & 0x3 {
+        1 => 3,
+        2 => 2,
+        3 => 1,
+        _ => return None,
+    };
+    let table = match (mpeg1, layer) {
+        (true, layer) => layer - 1,
+        (false, 1) => 3,
+        (false, _) => 4,
+    };
+    let bitrate = *MP3_BITRATES[table].get((frame[2] >> 4) as usize)?;
+    let base_rate = [44_100, 48_000, 32_000].get(((frame[2] >> 2) & 0x3) as usize)?;
+    let sample_rate = match version {
+        "MPEG-1" => *base_rate,
+        "MPEG-2" => base_rate / 2,
+        _ => base_rate / 4,
+    };
+    let mono = frame[3] >> 6 == 3;
+    let samples_per_frame = match (layer, mpeg1) {
+        (1, _) => 384,
+        (3, false) => 576,
+        _ => 1152,
+    };
+
+    let side_info = match (mpeg1, mono) {
+        (true, false) => 32,
+        (true, true) | (false, false) => 17,
+        (false, true) => 9,
+    };
+    let xing = frame_start + 4 + side_info;
+    let frames = match head.get(xing..xing + 4) {
+        Some(b"Xing") | Some(b"Info") if be_u32(head, xing + 4)? & 0x1 != 0 => {
+            be_u32(head, xing + 8)
+        }
+        _ => None,
+    };
+    let duration = match frames {
+        Some(frames) => Some(frames as f64 * samples_per_frame as f64 / sample_rate as f64),
+        None if bitrate > 0 => {
+            Some((file_size - frame_start as u64) as f64 * 8.0 / (bitrate as f64 * 1000.0))
+        }
+        None => None,
+    };
+
+    let mut info = MediaInfo::new(format!("MP3 audio ({} layer {})", version, layer));
+    info.duration = duration;
+    info.details.push((
+        "Audio",
+        format!(
+            "{} Hz, {}",
+            sample_rate,
+            if mono { "mono" } else { "stereo" }
+        ),
+    ));
+    if bitrate > 0 {
+        let bitrate = match frames {
+            Some(_) => format!("{} kb/s (first frame)", bitrate),
+            None => format!("{} kb/s", bitrate),
+        };
+        info.details.push(("Bitrate", bitrate));
+    }
+    Some(info)
+}
+
+/// The children of an ISO base media box as (type, contents) pairs
+fn mp4_boxes(mut bytes: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut boxes = Vec::new();
+    while let (Some(size), Some(kind)) = (be_u32(bytes, 0), bytes.get(4..8)) {
+        let (header, size) = match size {
+            0 => (8, bytes.len()),
+            1 => match be_u64(bytes, 8) {
+                Some(size) => (16, size as usize),
+                None => break,
+            },
+            size => (8, size as usize),
+        };
+        if size < header || size > bytes.len() {
+            break;
+        }
+        boxes.push((kind, &bytes[header..size]));
+        bytes = &bytes[size..];
+    }
+
+    boxes
+}
+
+fn mp4_info<R: Read + Seek>(file: &mut R) -> io::Result<Option<MediaInfo>> {
+    let mut brand = None;
+    let mut header = [0; 16];
+    // Walks the top level boxes, skipping over the media data to reach a `moov` at the end
+    let movie = loop {
+        let position = file.stream_position()?;
+        if file.read_exact(&mut header[..8]).is_err() {
+            return Ok(None);
+        }
+        let (size, header_length) = match be_u32(&header, 0).unwrap_or_default() {
+            1 => {
+                file.read_exact(&mut header[8..])?;
+                (be_u64(&header, 8).unwrap_or_default(), 16)
+            }
+            size => (size as u64, 8),
+        };
+        if size < header_length {
+            return Ok(None);
+        }
+        let length = size - header_length;
+
+        match &header[4..8] {
+            b"ftyp" => {
+                let mut ftyp = vec![0; length.min(64) as usize];
+                file.read_exact(&mut ftyp)?;
+                brand = ftyp.get(..4).map(|brand| brand.to_vec());
+                if !skip(file, length - ftyp.len() as u64)? {
+                    return Ok(None);
+                }
+            }
+            b"moov" if length <= MAX_MOVIE_HEADER_LENGTH => {
+                let mut movie = vec![0; length as usize];
+                file.read_exact(&mut movie)?;
+                break movie;
+            }
+            _ => {
+                if !skip(file, length)? {
+                    return Ok(None);
+                }
+            }
+        }
+        if file.stream_position()? <= position {
+            return Ok(None);
+        }
+    };
+
+    let format = match brand.as_deref() {
+        Some(b"qt  ") => "QuickTime movie",
+        Some(b"M4A ") => "MPEG-4 audio",
+        _ => "MPEG-4 video",
+    };
+    let mut info = MediaInfo::new(format);
+    for (kind, contents) in mp4_boxes(&movie) {
+        match kind {
+            b"mvhd" => {
+                let (created, timescale, duration) = match contents.first() {
+                    Some(1) => (
+                        be_u64(contents, 4),
+                        be_u32(contents, 20),
+                        be_u64(contents, 24),
+                    ),
+                    _ => (
+                        be_u32(contents, 4).map(u64::from),
+                        be_u32(contents, 12),
+                        be_u32(contents, 16).map(u64::from),
+                    ),
+                };
+                if let (Some(timescale), Some(duration)) = (timescale, duration) {
+                    if timescale > 0 {
+                        info.duration = Some(duration as f64 / timescale as f64);
+                    }
+                }
+                info.created = created
+                    .and_then(|created| created.checked_sub(QUICKTIME_EPOCH_OFFSET))
+                    .filter(|created| *created > 0)
+                    .map(format_timestamp);
+            }
+            b"trak" if info.dimensions.is_none() => {
+                // The track header ends with the width and height as 16.16 fixed point numbers
+                let dimensions = mp4_boxes(contents)
+                    .into_iter()
+                    .find(|(kind, _)| *kind == b"tkhd")
+                    .and_then(|(_, tkhd)| {
+                        let end = tkhd.len().checked_sub(8)?;
+                        Some((be_u32(tkhd, end)? >> 16, be_u32(tkhd, end + 4)? >> 16))
+                    });
+                info.dimensions = dimensions.filter(|(width, height)| *width > 0 && *height > 0);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Some(info))
+}
+
+/// Parses the formats that keep everything of interest within the first `HEAD_LENGTH` bytes
+fn head_info(kind: MediaKind, head: &[u8], size: u64) -> Option<MediaInfo> {
+    match kind {
+        MediaKind::Png => png_info(head),
+        MediaKind::Gif => gif_info(head),
+        MediaKind::Bmp => bmp_info(head),
+        MediaKind::Webp => webp_info(head),
+        MediaKind::Flac => flac_info(head),
+        MediaKind::Mp3 => mp3_info(head, size),
+        MediaKind::Jpeg | MediaKind::Wav | MediaKind::Mp4 => None,
+    }
+}
+
+/// A summary of the dimensions, duration and dates found in the headers of a media file
+pub fn describe(path: &Path, kind: MediaKind) -> Option<String> {
+    let file = File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut file = BufReader::new(file);
+
+    let parsed = match kind {
+        MediaKind::Jpeg => jpeg_info(&mut file),
+        MediaKind::Wav => wav_info(&mut file),
+        MediaKind::Mp4 => mp4_info(&mut file),
+        _ => {
+            let mut head = Vec::with_capacity(HEAD_LENGTH);
+            file.take(HEAD_LENGTH as u64)
+                .read_to_end(&mut head)
+                .map(|_| head_info(kind, &head, size))
+        }
+    };
+
+    match parsed {
+        Ok(info) => info.map(|info| info.render(size)),
+        Err(err) => {
+            log::warn!(
+                "Failed reading media headers of {}: {}",
+                path.display(),
+                err
+            );
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+        let mut bytes = (contents.len() as u32 + 8).to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(contents);
+        bytes
+    }
+
+    /// A box with a 64 bit size, which isn't checked against the contents that follow
+    fn large_mp4_box(kind: &[u8; 4], size: u64) -> Vec<u8> {
+        let mut bytes = 1u32.to_be_bytes().to_vec();
+        bytes.extend_from_slice(kind);
+        bytes.extend_from_slice(&size.to_be_bytes());
+        bytes
+    }
+
+    fn ftyp() -> Vec<u8> {
+        mp4_box(b"ftyp", b"isom\0\0\x02\0isomiso2")
+    }
+
+    fn mvhd() -> Vec<u8> {
+        let mut contents = vec![0; 100];
+        contents[12..16].copy_from_slice(&1000u32.to_be_bytes());
+        contents[16..20].copy_from_slice(&5000u32.to_be_bytes());
+        mp4_box(b"mvhd", &contents)
+    }
+
+    fn wav(chunks: &[u8]) -> Vec<u8> {
+        let mut bytes = b"RIFF".to_vec();
+        bytes.extend_from_slice(&(chunks.len() as u32 + 4).to_le_bytes());
+        bytes.extend_from_slice(b"WAVE");
+        bytes.extend_from_slice(chunks);
+        bytes
+    }
+
+    fn wav_chunk(kind: &[u8; 4], size: u32, contents: &[u8]) -> Vec<u8> {
+        let mut bytes = kind.to_vec();
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(contents);
+        bytes
+    }
+
+    fn wav_format() -> Vec<u8> {
+        let mut format = Vec::new();
+        format.extend_from_slice(&1u16.to_le_bytes());
+        format.extend_from_slice(&2u16.to_le_bytes());
+        format.extend_from_slice(&44_100u32.to_le_bytes());
+        format.extend_from_slice(&176_400u32.to_le_bytes());
+        format.extend_from_slice(&4u16.to_le_bytes());
+        format.extend_from_slice(&16u16.to_le_bytes());
+        wav_chunk(b"fmt ", 16, &format)
+    }
+
+    #[test]
+    fn reads_mp4_duration() {
+        let mut bytes = ftyp();
+        bytes.extend(mp4_box(b"mdat", &[0; 32]));
+        bytes.extend(mp4_box(b"moov", &mvhd()));
+
+        let info = mp4_info(&mut Cursor::new(bytes)).unwrap().unwrap();
+        assert_eq!(info.duration, Some(5.0));
+    }
+
+    #[test]
+    fn stops_at_mp4_boxes_too_large_to_seek_over() {
+        let mut bytes = ftyp();
+        bytes.extend(large_mp4_box(b"mdat", u64::MAX));
+        bytes.extend(mp4_box(b"moov", &mvhd()));
+
+        assert!(mp4_info(&mut Cursor::new(bytes)).unwrap().is_none());
+    }
+
+    #[test]
+    fn stops_at_mp4_boxes_seeking_backwards() {
+        // The size wraps around to a negative offset when cast to a signed one
+        let mut bytes = ftyp();
+        bytes.extend(large_mp4_box(b"mdat", u64::MAX - 7));
+        bytes.extend(mp4_box(b"moov", &mvhd()));
+
+        assert!(mp4_info(&mut Cursor::new(bytes)).unwrap().is_none());
+    }
+
+    #[test]
+    fn stops_at_mp4_boxes_smaller_than_their_header() {
+        let mut bytes = ftyp();
+        bytes.extend(4u32.to_be_bytes());
+        bytes.extend(b"mdat");
+
+        assert!(mp4_info(&mut Cursor::new(bytes)).unwrap().is_none());
+    }
+
+    #[test]
+    fn fails_on_truncated_mp4_movies() {
+        let mut bytes = ftyp();
+        bytes.extend(mp4_box(b"moov", &mvhd()));
+        bytes.truncate(bytes.len() - 10);
+
+        assert!(mp4_info(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn skips_mp4_children_past_their_parent() {
+        let mut contents = mvhd();
+        contents.extend(large_mp4_box(b"trak", u64::MAX));
+        contents.extend(mp4_box(b"udta", &[0; 4]));
+
+        let kinds = mp4_boxes(&contents)
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, [b"mvhd"]);
+    }
+
+    #[test]
+    fn reads_wav_duration() {
+        let mut chunks = wav_format();
+        chunks.extend(wav_chunk(b"data", 352_800, &[]));
+
+        let info = wav_info(&mut Cursor::new(wav(&chunks))).unwrap().unwrap();
+        assert_eq!(info.duration, Some(2.0));
+    }
+
+    #[test]
+    fn skips_oversized_wav_chunks() {
+        let mut chunks = wav_format();
+        chunks.extend(wav_chunk(b"LIST", u32::MAX, &[0; 4]));
+        chunks.extend(wav_chunk(b"data", 352_800, &[]));
+
+        let info = wav_info(&mut Cursor::new(wav(&chunks))).unwrap().unwrap();
+        assert_eq!(info.duration, None);
+    }
+
+    #[test]
+    fn fails_on_truncated_wav_formats() {
+        let mut chunks = wav_format();
+        chunks.truncate(12);
+
+        assert!(wav_info(&mut Cursor::new(wav(&chunks))).is_err());
+    }
+
+    #[test]
+    fn fails_on_truncated_jpeg_segments() {
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xe0];
+        bytes.extend(100u16.to_be_bytes());
+        bytes.extend([0; 10]);
+
+        assert!(jpeg_info(&mut Cursor::new(bytes)).is_err());
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions_after_skipped_segments() {
+        let mut bytes = vec![0xff, 0xd8, 0xff, 0xe0];
+        bytes.extend(6u16.to_be_bytes());
+        bytes.extend([0; 4]);
+        bytes.extend([0xff, 0xc0]);
+        bytes.extend(17u16.to_be_bytes());
+        bytes.extend([8, 0, 48, 0, 64, 3]);
+        bytes.extend([0; 9]);
+
+        let info = jpeg_info(&mut Cursor::new(bytes)).unwrap().unwrap();
+        assert_eq!(info.dimensions, Some((64, 48)));
+    }
+}
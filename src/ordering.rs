@@ -0,0 +1,95 @@
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaValue},
+    FromLua, IntoLua,
+};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::frecency::Frecency;
+use crate::picker::Entry;
+
+/// Orders every entry while the query is empty, applied in the configured order with the walk
+/// order as the final fallback
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum EmptyQueryOrder {
+    /// Files most recently opened from the picker first, then the rest of `v:oldfiles`
+    RecentlyOpened,
+    /// Files most recently modified on disk first, requires `metadata = true`
+    RecentlyModified,
+    /// Files with uncommitted git changes first
+    GitChanged,
+    /// Files open in a buffer first
+    OpenBuffers,
+}
+
+impl FromLua<'_> for EmptyQueryOrder {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                EmptyQueryOrder::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid empty query order: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Empty query orders must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for EmptyQueryOrder {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+pub struct OrderingContext<'a> {
+    pub frecency: &'a Frecency,
+    pub git_changes: &'a HashSet<String>,
+    pub open_buffers: &'a HashSet<String>,
+    pub oldfiles: &'a HashMap<String, usize>,
+}
+
+impl EmptyQueryOrder {
+    pub fn compare<T: Entry>(&self, a: &T, b: &T, context: &OrderingContext) -> Ordering {
+        match self {
+            EmptyQueryOrder::RecentlyOpened => {
+                let oldfile_rank = |entry: &T| context.oldfiles.get(entry.path()).copied();
+                context
+                    .frecency
+                    .last_visit(b.path())
+                    .cmp(&context.frecency.last_visit(a.path()))
+                    .then_with(|| match (oldfile_rank(a), oldfile_rank(b)) {
+                        (Some(a), Some(b)) => a.cmp(&b),
+                        (a, b) => b.is_some().cmp(&a.is_some()),
+                    })
+            }
+            EmptyQueryOrder::RecentlyModified => b.mtime().cmp(&a.mtime()),
+            EmptyQueryOrder::GitChanged => context
+                .git_changes
+                .contains(b.path())
+                .cmp(&context.git_changes.contains(a.path())),
+            EmptyQueryOrder::OpenBuffers => context
+                .open_buffers
+                .contains(b.path())
+                .cmp(&context.open_buffers.contains(a.path())),
+        }
+    }
+}
+
+/// Compares with each ordering in turn until one of them tells the entries apart
+pub fn compare_all<T: Entry>(
+    orders: &[EmptyQueryOrder],
+    a: &T,
+    b: &T,
+    context: &OrderingContext,
+) -> Ordering {
+    orders
+        .iter()
+        .map(|order| order.compare(a, b, context))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
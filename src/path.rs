@@ -0,0 +1,66 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use mlua::prelude::{Lua, LuaFunction, LuaResult, LuaTable};
+use serde::Serialize;
+
+/// The platform encoding of `path`, raw bytes on Unix
+pub fn to_bytes(path: &Path) -> Vec<u8> {
+    path.as_os_str().as_encoded_bytes().to_vec()
+}
+
+/// Rebuilds a path from bytes handed back from Lua, which may not be valid UTF-8
+#[cfg(unix)]
+pub fn from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    PathBuf::from(OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+pub fn from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).to_string())
+}
+
+/// Writes `value` with bincode to a temporary file next to `path` and renames it into place, so
+/// that readers never see a partially written file
+pub fn write_atomically<T: Serialize>(path: &Path, value: &T) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+
+    let tmp_path = path.with_extension("tmp");
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    bincode::serialize_into(&mut writer, value).map_err(io::Error::other)?;
+    writer.flush()?;
+    drop(writer);
+    fs::rename(&tmp_path, path)
+}
+
+/// Number of directory hops from `dir` to the directory containing `path`
+pub fn directory_distance(dir: &Path, path: &Path) -> usize {
+    let parent = path.parent().unwrap_or(path);
+    let common = dir
+        .components()
+        .zip(parent.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    (dir.components().count() - common) + (parent.components().count() - common)
+}
+
+/// Resolves Neovim's `stdpath(what)`, falling back to the system temp dir outside of Neovim
+pub fn stdpath(lua: &Lua, what: &str) -> PathBuf {
+    let stdpath = || -> LuaResult<String> {
+        let vim: LuaTable = lua.globals().get("vim")?;
+        let vim_fn: LuaTable = vim.get("fn")?;
+        vim_fn.get::<_, LuaFunction>("stdpath")?.call(what)
+    };
+
+    match stdpath() {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => std::env::temp_dir(),
+    }
+}
@@ -1,8 +1,11 @@
 use std::cmp::{max, min};
-use std::collections::BTreeSet;
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{self, AtomicBool, AtomicUsize};
 use std::sync::Arc;
+use std::time::UNIX_EPOCH;
 
 use crossbeam_channel::bounded;
 use mlua::{
@@ -19,16 +22,69 @@ use serde::{Deserialize, Serialize};
 use strum::{Display, EnumString};
 
 use crate::buffer::{BufferContents, Contents, Cursor, Relative, Window};
-use crate::injector::Injector;
+use crate::error::{Error, Result};
+use crate::frecency::Frecency;
+use crate::injector::{Injector, Root, Source, WalkOptions};
+use crate::ordering::{self, EmptyQueryOrder, OrderingContext};
+use crate::quickfix::{QuickfixItem, QuickfixSource};
+use crate::tiebreak::{compare_all, Tiebreak, TiebreakContext};
 
 pub trait Entry: Serialize + Clone + Sync + Send + 'static {
     fn into_utf32(self) -> Utf32String;
-    fn from_path(path: &Path, cwd: Option<String>) -> Self;
+    fn path(&self) -> &str;
+    /// The path as the platform encodes it, which `path` may have lost when it isn't UTF-8
+    fn raw_path(&self) -> &[u8];
+    fn match_value(&self) -> &str;
+    fn mtime(&self) -> Option<u64>;
+    fn from_path(path: &Path, cwd: Option<String>) -> Result<Self>;
+    fn with_metadata(self, metadata: FileMetadata) -> Self;
+    fn with_kind(self, kind: EntryKind) -> Self;
+    fn with_root(self, root: String) -> Self;
     fn set_selected(&mut self, selected: bool);
     fn with_indices(self, indices: Vec<(u32, u32)>) -> Self;
+    fn with_score(self, score: u32) -> Self;
+    fn with_boost(self, boost: u32) -> Self;
     fn with_selected(self, selected: bool) -> Self;
 }
 
+/// Largest distance past the window edges that a tie is followed when applying tiebreaks
+const MAX_TIE_SPAN: u32 = 10_000;
+/// Number of leading matches that are reranked by their proximity boosted score
+const MAX_BOOSTED_MATCHES: u32 = 1_000;
+
+/// Item indices in empty query display order
+#[derive(Debug, Clone)]
+struct EmptyQueryCache {
+    /// Items covered by `order`
+    item_count: u32,
+    /// Whether `order` was sorted, items arriving during a walk are appended in walk order
+    sorted: bool,
+    order: Arc<Vec<u32>>,
+}
+
+/// A run of equally scored matches in display order, as (score, position in nucleo's order)
+#[derive(Debug)]
+struct TieRun {
+    end: u32,
+    ranked: Vec<(u32, u32)>,
+}
+
+/// Reranked matches as (score, position in nucleo's order), so that looking up single matches
+/// doesn't rerank everything around them again
+#[derive(Debug, Default)]
+struct RankCache {
+    /// The leading matches reranked by their boosted score
+    boosted: Option<Vec<(u32, u32)>>,
+    /// Runs of tied matches after the boosted ones, by the position they start at
+    ties: BTreeMap<u32, TieRun>,
+}
+
+/// Full `boost` for files next to the reference, falling off with every directory hop
+fn proximity_bonus(boost: u32, reference_dir: &Path, path: &Path) -> u32 {
+    let distance = crate::path::directory_distance(reference_dir, path);
+    boost / (distance as u32).saturating_add(1)
+}
+
 pub struct Matcher<T: Entry>(pub Nucleo<T>);
 
 #[derive(Default)]
@@ -90,11 +146,107 @@ pub enum Movement {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FileEntry {
+    /// Lossy UTF-8 form of the path, used for display
     pub path: String,
+    /// The original bytes of the path, only present when it isn't valid UTF-8
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_bytes")]
+    pub raw_path: Option<Vec<u8>>,
     pub match_value: String,
     pub file_type: String,
+    pub kind: EntryKind,
+    /// Label of the root the entry was found in, only set when the picker has several roots
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub root: Option<String>,
     pub selected: bool,
     pub indices: Vec<(u32, u32)>,
+    /// Match score as nucleo computed it, zero while the query is empty
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub score: Option<u32>,
+    /// Proximity bonus the match was ranked with on top of its score
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub boost: Option<u32>,
+    /// Only collected when the picker is configured with `metadata = true`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<FileMetadata>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    #[default]
+    File,
+    Directory,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileMetadata {
+    /// Size in bytes
+    pub size: u64,
+    /// Last modification time in seconds since the Unix epoch
+    pub mtime: Option<u64>,
+    /// Unix permission bits, e.g. `0o644`
+    pub permissions: Option<u32>,
+    pub readonly: bool,
+    pub is_executable: bool,
+    pub symlink_target: Option<String>,
+}
+
+impl FileMetadata {
+    pub fn from_dir_entry(entry: &ignore::DirEntry) -> Option<Self> {
+        Self::from_metadata(entry.path(), entry.metadata(), entry.path_is_symlink())
+    }
+
+    /// Reads the metadata of `path` from disk, following symlinks like the walker does
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let is_symlink =
+            std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink());
+        Self::from_metadata(path, std::fs::metadata(path), is_symlink)
+    }
+
+    fn from_metadata<E: std::fmt::Display>(
+        path: &Path,
+        metadata: std::result::Result<std::fs::Metadata, E>,
+        is_symlink: bool,
+    ) -> Option<Self> {
+        let metadata = match metadata {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                log::warn!("Failed reading metadata for {}: {}", path.display(), err);
+                return None;
+            }
+        };
+
+        let mtime = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|duration| duration.as_secs());
+
+        #[cfg(unix)]
+        let permissions = {
+            use std::os::unix::fs::PermissionsExt;
+            Some(metadata.permissions().mode() & 0o7777)
+        };
+        #[cfg(not(unix))]
+        let permissions = None;
+
+        let symlink_target = if is_symlink {
+            std::fs::read_link(path)
+                .ok()
+                .map(|target| target.to_string_lossy().to_string())
+        } else {
+            None
+        };
+
+        Some(Self {
+            size: metadata.len(),
+            mtime,
+            permissions,
+            readonly: metadata.permissions().readonly(),
+            is_executable: permissions.is_some_and(|mode| mode & 0o111 != 0),
+            symlink_target,
+        })
+    }
 }
 
 impl Entry for FileEntry {
@@ -102,29 +254,85 @@ impl Entry for FileEntry {
         self.match_value.into()
     }
 
+    fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn raw_path(&self) -> &[u8] {
+        self.raw_path.as_deref().unwrap_or(self.path.as_bytes())
+    }
+
+    fn match_value(&self) -> &str {
+        &self.match_value
+    }
+
+    fn mtime(&self) -> Option<u64> {
+        self.metadata.as_ref().and_then(|metadata| metadata.mtime)
+    }
+
     fn with_indices(self, indices: Vec<(u32, u32)>) -> Self {
         Self { indices, ..self }
     }
 
-    fn from_path(path: &Path, cwd: Option<String>) -> FileEntry {
-        let full_path = path.to_str().expect("Failed to convert path to string");
+    fn with_score(self, score: u32) -> Self {
+        Self {
+            score: Some(score),
+            ..self
+        }
+    }
+
+    fn with_boost(self, boost: u32) -> Self {
+        Self {
+            boost: Some(boost),
+            ..self
+        }
+    }
+
+    fn from_path(path: &Path, cwd: Option<String>) -> Result<FileEntry> {
+        let root = cwd.unwrap_or_default();
         let match_value = path
-            .strip_prefix(&cwd.unwrap_or_default())
-            .expect("Failed to strip prefix")
-            .to_str()
-            .expect("Failed to convert path to string")
+            .strip_prefix(&root)
+            .map_err(|_| Error::OutsideRoot {
+                path: path.to_string_lossy().to_string(),
+                root: root.clone(),
+            })?
+            .to_string_lossy()
             .to_string();
 
-        Self {
+        Ok(Self {
             selected: false,
             match_value,
-            path: full_path.to_string(),
+            path: path.to_string_lossy().to_string(),
+            raw_path: path.to_str().is_none().then(|| crate::path::to_bytes(path)),
             indices: Vec::new(),
+            score: None,
+            boost: None,
+            metadata: None,
+            kind: EntryKind::File,
+            root: None,
             file_type: path
                 .extension()
                 .unwrap_or_default()
                 .to_string_lossy()
                 .to_string(),
+        })
+    }
+
+    fn with_metadata(self, metadata: FileMetadata) -> Self {
+        Self {
+            metadata: Some(metadata),
+            ..self
+        }
+    }
+
+    fn with_kind(self, kind: EntryKind) -> Self {
+        Self { kind, ..self }
+    }
+
+    fn with_root(self, root: String) -> Self {
+        Self {
+            root: Some(root),
+            ..self
         }
     }
 
@@ -172,17 +380,61 @@ impl<T: Entry> Contents for Matcher<T> {
     }
 }
 
+/// The state a reload from the index cache restores once the reloaded items are matched
+#[derive(Debug)]
+struct PendingReload {
+    /// The `walk_cancelled` flag of the reload, a restart or another reload supersedes it
+    generation: Arc<AtomicBool>,
+    /// Paths of the entries selected before the reload
+    selected: HashSet<String>,
+    cursor: usize,
+}
+
+impl PendingReload {
+    fn is_current(&self, walk_cancelled: &Arc<AtomicBool>) -> bool {
+        Arc::ptr_eq(&self.generation, walk_cancelled)
+            && !self.generation.load(atomic::Ordering::Relaxed)
+    }
+}
+
 pub struct Picker<T: Entry> {
     pub matcher: Matcher<T>,
     previous_query: String,
-    cwd: String,
+    roots: Vec<Root>,
     cursor: Cursor,
     window: Window,
     selections: BTreeSet<u32>,
     sender: crossbeam_channel::Sender<()>,
     receiver: crossbeam_channel::Receiver<()>,
-    git_ignore: bool,
+    walk_options: WalkOptions,
+    /// Set to stop the walk feeding the current items
+    walk_cancelled: Arc<AtomicBool>,
+    /// Where walked roots are cached between pickers, caching is off when `None`
+    cache_dir: Option<PathBuf>,
+    /// Walks of the current roots that have not finished yet
+    active_walks: Arc<AtomicUsize>,
+    /// Set when a walk found that cached entries were deleted
+    stale_cache: Arc<AtomicBool>,
+    /// What to restore once a reload from the index cache has been matched
+    pending_reload: Option<PendingReload>,
     sort_direction: SortDirection,
+    tiebreaks: Vec<Tiebreak>,
+    proximity_boost: u32,
+    /// Matches scoring below this are hidden, off when zero
+    min_score: u32,
+    /// The file the picker was opened from
+    reference_path: Option<String>,
+    frecency: Frecency,
+    /// Where visits are saved between sessions, kept in memory only when `None`
+    frecency_path: Option<PathBuf>,
+    /// Display order of the matches that boosts and tiebreaks rerank, until the snapshot changes
+    rank_cache: Mutex<RankCache>,
+    empty_query_order: Vec<EmptyQueryOrder>,
+    open_buffers: HashSet<String>,
+    /// Files recently opened in Neovim by their position in `v:oldfiles`, most recent first
+    oldfiles: HashMap<String, usize>,
+    git_changes: Arc<Mutex<HashSet<String>>>,
+    empty_query_cache: Arc<Mutex<Option<EmptyQueryCache>>>,
 }
 
 impl<T: Entry> Picker<T> {
@@ -198,11 +450,28 @@ impl<T: Entry> Picker<T> {
 
         Self {
             matcher,
-            cwd,
+            roots: vec![Root::new(cwd)],
             receiver,
             sender,
             sort_direction,
-            git_ignore: true,
+            walk_options: WalkOptions::default(),
+            walk_cancelled: Arc::default(),
+            cache_dir: None,
+            active_walks: Arc::default(),
+            stale_cache: Arc::default(),
+            pending_reload: None,
+            tiebreaks: Vec::new(),
+            proximity_boost: 0,
+            min_score: 0,
+            reference_path: None,
+            frecency: Frecency::default(),
+            frecency_path: None,
+            rank_cache: Mutex::default(),
+            empty_query_order: Vec::new(),
+            open_buffers: HashSet::new(),
+            oldfiles: HashMap::new(),
+            git_changes: Arc::default(),
+            empty_query_cache: Arc::default(),
             cursor: Cursor::default(),
             previous_query: String::new(),
             selections: BTreeSet::new(),
@@ -211,13 +480,52 @@ impl<T: Entry> Picker<T> {
     }
 
     pub fn tick(&mut self, timeout: u64) -> Status {
+        // Drop the deleted entries once every root has been walked into a fresh cache. This
+        // happens before the matcher ticks so that the snapshot never shows the old items once
+        // their indices are reused.
+        if self.active_walks.load(atomic::Ordering::Acquire) == 0
+            && self.stale_cache.swap(false, atomic::Ordering::AcqRel)
+        {
+            log::info!("Reloading stale index cache");
+            self.reload_from_cache();
+        }
+
+        // Read before ticking, so that a finished tick has matched everything the walks pushed
+        let walks_done = self.active_walks.load(atomic::Ordering::Acquire) == 0;
         let status = self.matcher.tick(timeout);
+        if status.0.changed {
+            *self.rank_cache.get_mut() = RankCache::default();
+        }
 
-        self.update_cursor();
+        match self.pending_reload.take() {
+            Some(reload) if !reload.is_current(&self.walk_cancelled) => self.update_cursor(),
+            Some(reload) if !walks_done || status.0.running => {
+                self.pending_reload = Some(reload);
+                self.update_cursor();
+            }
+            Some(reload) => self.finish_reload(reload),
+            None => self.update_cursor(),
+        }
 
         status
     }
 
+    /// Selects the matches of the paths that were selected before the reload, now that they
+    /// have their new positions
+    fn finish_reload(&mut self, reload: PendingReload) {
+        if !reload.selected.is_empty() {
+            let positions = self
+                .ordered_matches(0..self.total_matches())
+                .into_iter()
+                .enumerate()
+                .filter(|(_, item)| reload.selected.contains(item.data.path()))
+                .map(|(pos, _)| pos as u32)
+                .collect::<Vec<_>>();
+            self.selections.extend(positions);
+        }
+        self.set_cursor_pos(reload.cursor);
+    }
+
     fn try_recv(&self) -> Result<(), crossbeam_channel::TryRecvError> {
         self.receiver.try_recv()
     }
@@ -227,7 +535,38 @@ impl<T: Entry> Picker<T> {
     }
 
     pub fn total_matches(&self) -> u32 {
-        self.matcher.snapshot().matched_item_count()
+        self.visible_match_count(self.matcher.snapshot())
+    }
+
+    /// Number of matches that reach `min_score`. Nucleo orders matches by descending score,
+    /// so those form a prefix that can be found by bisecting.
+    fn visible_match_count(&self, snapshot: &nucleo::Snapshot<T>) -> u32 {
+        let total = snapshot.matched_item_count();
+        if self.min_score == 0 || snapshot.pattern().is_empty() {
+            return total;
+        }
+
+        let string_matcher = &mut STRING_MATCHER.lock().0;
+        let mut reaches_min_score = |n: u32| {
+            snapshot.get_matched_item(n).is_some_and(|item| {
+                snapshot
+                    .pattern()
+                    .score(item.matcher_columns, string_matcher)
+                    .is_some_and(|score| score >= self.min_score)
+            })
+        };
+
+        let (mut low, mut high) = (0, total);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if reaches_min_score(mid) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        low
     }
 
     pub fn total_items(&self) -> u32 {
@@ -269,21 +608,107 @@ impl<T: Entry> Picker<T> {
     }
 
     pub fn update_cwd(&mut self, cwd: &str) {
-        self.cwd = cwd.to_string();
+        let roots = vec![Root::new(cwd.to_string())];
+        if roots != self.roots {
+            self.roots = roots;
+            self.populate_files();
+        }
     }
 
-    pub fn update_config(&mut self, config: Config) {
-        // let cwd = match config.cwd {
-        //     Some(cwd) => cwd,
-        //     None => ;
+    pub fn roots(&self) -> &[Root] {
+        &self.roots
+    }
+
+    /// Applies `config`, re-indexing when the roots or walk options changed.
+    /// Returns whether a re-index was started.
+    pub fn update_config(&mut self, config: Config) -> bool {
+        let previous_roots = self.roots.clone();
+        let previous_walk_options = self.walk_options;
 
         if let Some(cwd) = config.cwd {
-            self.cwd = cwd;
+            self.roots = vec![Root::new(cwd)];
+        }
+
+        if let Some(roots) = config.roots.filter(|roots| !roots.is_empty()) {
+            self.roots = roots;
         }
 
         if let Some(sort_direction) = config.sort_direction {
             self.sort_direction = sort_direction;
         }
+
+        if let Some(source) = config.source {
+            self.walk_options.source = source;
+        }
+
+        if let Some(git_ignore) = config.git_ignore {
+            self.walk_options.git_ignore = git_ignore;
+        }
+
+        if let Some(metadata) = config.metadata {
+            self.walk_options.metadata = metadata;
+        }
+
+        if let Some(max_depth) = config.max_depth {
+            self.walk_options.max_depth = Some(max_depth).filter(|depth| *depth > 0);
+        }
+
+        if let Some(exclude_compressed) = config.exclude_compressed {
+            self.walk_options.exclude_compressed = exclude_compressed;
+        }
+
+        if let Some(cache) = config.cache {
+            self.cache_dir = config.cache_dir.filter(|_| cache).map(PathBuf::from);
+        }
+
+        if let Some(tiebreaks) = config.tiebreak {
+            self.tiebreaks = tiebreaks;
+        }
+
+        if let Some(proximity_boost) = config.proximity_boost {
+            self.proximity_boost = proximity_boost;
+        }
+
+        if let Some(min_score) = config.min_score {
+            self.min_score = min_score;
+        }
+
+        if let Some(reference_path) = config.reference_path {
+            self.reference_path = Some(reference_path).filter(|path| !path.is_empty());
+        }
+
+        if let Some(empty_query_order) = config.empty_query_order {
+            self.empty_query_order = empty_query_order;
+        }
+
+        if let Some(open_buffers) = config.open_buffers {
+            self.open_buffers = open_buffers.into_iter().collect();
+        }
+
+        if let Some(oldfiles) = config.oldfiles {
+            self.oldfiles = oldfiles
+                .into_iter()
+                .enumerate()
+                .map(|(rank, path)| (path, rank))
+                .collect();
+        }
+
+        if let Some(frecency_path) = config.frecency_path.map(PathBuf::from) {
+            if self.frecency_path.as_ref() != Some(&frecency_path) {
+                self.frecency = Frecency::load(&frecency_path);
+                self.frecency_path = Some(frecency_path);
+            }
+        }
+
+        self.empty_query_cache.lock().take();
+        *self.rank_cache.get_mut() = RankCache::default();
+
+        let reindex = self.roots != previous_roots || self.walk_options != previous_walk_options;
+        if reindex {
+            self.populate_files();
+        }
+
+        reindex
     }
 
     pub fn move_cursor(&mut self, direction: Movement, change: u32) {
@@ -334,19 +759,264 @@ impl<T: Entry> Picker<T> {
         log::info!("Selection index: {}", self.cursor.pos());
     }
 
+    /// Returns the matches in `range` in display order along with their scores and proximity
+    /// bonuses.
+    ///
+    /// Display order is nucleo's order, except that the leading matches are reranked by their
+    /// proximity boosted score and runs of equally scored matches are reordered by the
+    /// configured tiebreaks.
+    pub fn scored_matches(&self, range: Range<u32>) -> Vec<(u32, u32, nucleo::Item<'_, T>)> {
+        let snapshot = self.matcher.snapshot();
+        let total = self.visible_match_count(snapshot);
+        let range = range.start.min(total)..range.end.min(total);
+        if range.is_empty() {
+            return Vec::new();
+        }
+
+        let reference_dir = self
+            .reference_path
+            .as_deref()
+            .and_then(|path| Path::new(path).parent());
+        let boost = match reference_dir {
+            Some(dir) if self.proximity_boost > 0 && !snapshot.pattern().is_empty() => {
+                Some((dir, self.proximity_boost))
+            }
+            _ => None,
+        };
+        let bonus = |entry: &T| match boost {
+            Some((dir, boost)) => proximity_bonus(boost, dir, Path::new(entry.path())),
+            None => 0,
+        };
+
+        if snapshot.pattern().is_empty() && !self.empty_query_order.is_empty() {
+            let order = self.empty_query_order(snapshot);
+            return order[range.start as usize..range.end as usize]
+                .iter()
+                .filter_map(|idx| snapshot.get_item(*idx))
+                .map(|item| (0, 0, item))
+                .collect();
+        }
+
+        let string_matcher = &mut STRING_MATCHER.lock().0;
+        let mut score = |item: &nucleo::Item<'_, T>| {
+            snapshot
+                .pattern()
+                .score(item.matcher_columns, string_matcher)
+                .unwrap_or_default()
+        };
+
+        if snapshot.pattern().is_empty() || (self.tiebreaks.is_empty() && boost.is_none()) {
+            return snapshot
+                .matched_items(range)
+                .map(|item| (score(&item), bonus(item.data), item))
+                .collect();
+        }
+
+        let context = TiebreakContext {
+            reference_dir,
+            frecency: &self.frecency,
+            now: crate::frecency::now(),
+        };
+        // Ranks (score, position) pairs, nucleo's order already has the scores descending
+        let rank = |positions: Range<u32>, score: &mut dyn FnMut(&nucleo::Item<'_, T>) -> u32| {
+            let mut items = positions
+                .clone()
+                .zip(snapshot.matched_items(positions))
+                .map(|(n, item)| (score(&item), n, item))
+                .collect::<Vec<_>>();
+            items.sort_by(|(score_a, _, a), (score_b, _, b)| {
+                score_b
+                    .cmp(score_a)
+                    .then_with(|| compare_all(&self.tiebreaks, a.data, b.data, &context))
+            });
+            items
+        };
+
+        let mut cache = self.rank_cache.lock();
+        let mut ranked = Vec::with_capacity(range.len());
+
+        // A boost can lift a match over any number of slightly better ones, so the leading
+        // matches are reranked as a whole to keep the order the same for every window
+        let boosted_len = match boost {
+            Some(_) => MAX_BOOSTED_MATCHES.min(total),
+            None => 0,
+        };
+        if range.start < boosted_len {
+            let boosted = cache.boosted.get_or_insert_with(|| {
+                rank(0..boosted_len, &mut |item| score(item) + bonus(item.data))
+                    .into_iter()
+                    .map(|(score, n, item)| (score - bonus(item.data), n))
+                    .collect()
+            });
+            ranked.extend_from_slice(
+                &boosted[range.start as usize..range.end.min(boosted_len) as usize],
+            );
+        }
+
+        let mut n = range.start.max(boosted_len);
+        while n < range.end {
+            let run_start = match cache.ties.range(..=n).next_back() {
+                Some((start, run)) if run.end > n => *start,
+                previous => {
+                    // Runs are widened to the whole tie so that scrolling is stable, without
+                    // overlapping the runs ranked before
+                    let lower = previous.map_or(boosted_len, |(_, run)| run.end.max(boosted_len));
+                    let upper = cache
+                        .ties
+                        .range(n + 1..)
+                        .next()
+                        .map_or(total, |(start, _)| *start);
+                    let mut score_at =
+                        |n: u32| snapshot.get_matched_item(n).map(|item| score(&item));
+                    let tie_score = score_at(n);
+
+                    let mut start = n;
+                    let floor = n.saturating_sub(MAX_TIE_SPAN).max(lower);
+                    while start > floor && score_at(start - 1) == tie_score {
+                        start -= 1;
+                    }
+                    let mut end = n + 1;
+                    let ceiling = end.saturating_add(MAX_TIE_SPAN).min(upper);
+                    while end < ceiling && score_at(end) == tie_score {
+                        end += 1;
+                    }
+
+                    let ranked = rank(start..end, &mut score)
+                        .into_iter()
+                        .map(|(score, n, _)| (score, n))
+                        .collect();
+                    cache.ties.insert(start, TieRun { end, ranked });
+                    start
+                }
+            };
+
+            let run = &cache.ties[&run_start];
+            let end = range.end.min(run.end);
+            ranked.extend_from_slice(
+                &run.ranked[(n - run_start) as usize..(end - run_start) as usize],
+            );
+            n = end;
+        }
+        drop(cache);
+
+        ranked
+            .into_iter()
+            .filter_map(|(score, n)| {
+                snapshot
+                    .get_matched_item(n)
+                    .map(|item| (score, bonus(item.data), item))
+            })
+            .collect()
+    }
+
+    /// Item indices sorted by the configured empty query orders. Sorting waits for the walks to
+    /// finish, items arriving until then are shown in walk order after the sorted ones.
+    fn empty_query_order(&self, snapshot: &nucleo::Snapshot<T>) -> Arc<Vec<u32>> {
+        let item_count = snapshot.item_count();
+        let walking = self.active_walks.load(atomic::Ordering::Acquire) > 0;
+        let unsorted = |range: Range<u32>| range.filter(|idx| snapshot.get_item(*idx).is_some());
+
+        {
+            let mut cache = self.empty_query_cache.lock();
+            match cache.as_mut() {
+                Some(cache) if cache.item_count == item_count && (cache.sorted || walking) => {
+                    return cache.order.clone();
+                }
+                Some(cache) if walking && cache.item_count < item_count => {
+                    Arc::make_mut(&mut cache.order).extend(unsorted(cache.item_count..item_count));
+                    cache.item_count = item_count;
+                    return cache.order.clone();
+                }
+                _ if walking => {
+                    let order = Arc::new(unsorted(0..item_count).collect::<Vec<_>>());
+                    *cache = Some(EmptyQueryCache {
+                        item_count,
+                        sorted: false,
+                        order: order.clone(),
+                    });
+                    return order;
+                }
+                _ => {}
+            }
+        }
+
+        // No locks are held while sorting, the parallel sort may run other calls to this on the
+        // same thread
+        let git_changes = self.git_changes.lock().clone();
+        let context = OrderingContext {
+            frecency: &self.frecency,
+            git_changes: &git_changes,
+            open_buffers: &self.open_buffers,
+            oldfiles: &self.oldfiles,
+        };
+        let mut order = unsorted(0..item_count).collect::<Vec<_>>();
+        order.par_sort_by(
+            |a, b| match (snapshot.get_item(*a), snapshot.get_item(*b)) {
+                (Some(a), Some(b)) => {
+                    ordering::compare_all(&self.empty_query_order, a.data, b.data, &context)
+                }
+                _ => std::cmp::Ordering::Equal,
+            },
+        );
+
+        let order = Arc::new(order);
+        *self.empty_query_cache.lock() = Some(EmptyQueryCache {
+            item_count,
+            sorted: true,
+            order: order.clone(),
+        });
+        order
+    }
+
+    /// Returns the matches in `range` in display order
+    pub fn ordered_matches(&self, range: Range<u32>) -> Vec<nucleo::Item<'_, T>> {
+        self.scored_matches(range)
+            .into_iter()
+            .map(|(_, _, item)| item)
+            .collect()
+    }
+
+    /// The `n`th match in display order
+    pub fn get_matched_item(&self, n: u32) -> Option<nucleo::Item<'_, T>> {
+        self.ordered_matches(n..n.saturating_add(1))
+            .into_iter()
+            .next()
+    }
+
+    pub fn record_open(&mut self, path: String) {
+        self.frecency.record(path, crate::frecency::now());
+        if let Some(frecency_path) = &self.frecency_path {
+            if let Err(err) = self.frecency.save(frecency_path) {
+                log::error!("{}", err);
+            }
+        }
+        self.empty_query_cache.lock().take();
+        *self.rank_cache.get_mut() = RankCache::default();
+    }
+
     pub fn current_matches(&self) -> Vec<T> {
-        let mut indices = Vec::new();
         let snapshot = self.matcher.snapshot();
         log::info!("Item count: {:?}", snapshot.item_count());
         log::info!("Match count: {:?}", snapshot.matched_item_count());
-        let string_matcher = &mut STRING_MATCHER.lock().0;
 
-        let lower_bound = self.lower_bound();
-        let upper_bound = self.upper_bound();
+        self.highlighted_matches(self.lower_bound()..self.upper_bound())
+    }
+
+    /// Up to `limit` matches from `offset` on, regardless of the window
+    pub fn matches(&self, offset: u32, limit: u32) -> Vec<T> {
+        self.highlighted_matches(offset..offset.saturating_add(limit))
+    }
+
+    /// The matches in `range` with their scores and the indices of the matched characters
+    fn highlighted_matches(&self, range: Range<u32>) -> Vec<T> {
+        let mut indices = Vec::new();
+        let snapshot = self.matcher.snapshot();
+        let items = self.scored_matches(range);
+        let string_matcher = &mut STRING_MATCHER.lock().0;
 
-        snapshot
-            .matched_items(lower_bound..upper_bound)
-            .map(|item| {
+        items
+            .into_iter()
+            .map(|(score, boost, item)| {
                 snapshot.pattern().column_pattern(0).indices(
                     item.matcher_columns[0].slice(..),
                     string_matcher,
@@ -360,24 +1030,139 @@ impl<T: Entry> Picker<T> {
                     .into_par_iter()
                     .map(|range| range.into_inner());
                 // TODO: Probably a better way to do this
-                item.data.clone().with_indices(ranges.collect())
+                item.data
+                    .clone()
+                    .with_indices(ranges.collect())
+                    .with_score(score)
+                    .with_boost(boost)
             })
             .collect::<Vec<_>>()
     }
 
+    /// Drops every item and stops any walk that is still running
     pub fn restart(&mut self) {
+        self.walk_cancelled.store(true, atomic::Ordering::Relaxed);
         self.matcher.0.restart(true);
+        self.selections.clear();
+        self.cursor.set_pos(0);
+        self.empty_query_cache.lock().take();
+        *self.rank_cache.get_mut() = RankCache::default();
+        self.stale_cache.store(false, atomic::Ordering::Release);
+        self.pending_reload = None;
     }
 
-    pub fn populate_files(&mut self) {
-        let dir = self.cwd.clone();
-        let git_ignore = self.git_ignore;
-        let injector = self.matcher.injector();
+    /// Replaces the items with the refreshed index caches of the roots without walking them
+    /// again, keeping the cursor and the selections that still exist
+    fn reload_from_cache(&mut self) {
+        let Some(cache_dir) = self.cache_dir.clone() else {
+            return;
+        };
+        let selected = self
+            .selections()
+            .iter()
+            .map(|entry| entry.path().to_string())
+            .collect::<HashSet<_>>();
+
+        self.walk_cancelled.store(true, atomic::Ordering::Relaxed);
+        self.walk_cancelled = Arc::new(AtomicBool::new(false));
+        self.matcher.0.restart(true);
+        self.selections.clear();
+        self.pending_reload = Some(PendingReload {
+            generation: self.walk_cancelled.clone(),
+            selected,
+            cursor: self.cursor.pos(),
+        });
+        self.empty_query_cache.lock().take();
+        *self.rank_cache.get_mut() = RankCache::default();
+
+        let labelled = self.roots.len() > 1;
+        for root in self.roots.iter().cloned() {
+            let options = self.walk_options;
+            let injector = self.matcher.injector();
+            let label = labelled.then_some(root.label);
+            let cancelled = self.walk_cancelled.clone();
+            let cache_dir = cache_dir.clone();
+            let active_walks = self.active_walks.clone();
+            active_walks.fetch_add(1, atomic::Ordering::AcqRel);
+            std::thread::spawn(move || {
+                injector.populate_from_cache(root.path, label, options, cancelled, cache_dir);
+                active_walks.fetch_sub(1, atomic::Ordering::AcqRel);
+            });
+        }
+    }
+
+    /// Reads the git status of the picker roots in the background for the `git_changed` order
+    fn refresh_git_changes(&self) {
+        let dirs = self
+            .roots
+            .iter()
+            .map(|root| root.path.clone())
+            .collect::<Vec<_>>();
+        let git_changes = self.git_changes.clone();
+        let empty_query_cache = self.empty_query_cache.clone();
+        let sender = self.sender.clone();
         std::thread::spawn(move || {
-            injector.populate_files_sorted(dir, git_ignore);
+            let mut changes = HashSet::new();
+            for dir in dirs {
+                match crate::git::changed_files(Path::new(&dir)) {
+                    Ok(root_changes) => changes.extend(root_changes),
+                    Err(err) => log::warn!("Failed reading git changes for {}: {}", dir, err),
+                }
+            }
+            *git_changes.lock() = changes;
+            empty_query_cache.lock().take();
+            let _ = sender.try_send(());
         });
     }
 
+    /// Re-indexes the roots from scratch, replacing the current items
+    pub fn populate_files(&mut self) {
+        self.restart();
+        self.walk_cancelled = Arc::new(AtomicBool::new(false));
+
+        if self
+            .empty_query_order
+            .contains(&EmptyQueryOrder::GitChanged)
+        {
+            self.refresh_git_changes();
+        }
+
+        // Labels are only worth their memory when there is more than one root to tell apart
+        let labelled = self.roots.len() > 1;
+        for root in self.roots.iter().cloned() {
+            let options = self.walk_options;
+            let injector = self.matcher.injector();
+            let label = labelled.then_some(root.label);
+            let cancelled = self.walk_cancelled.clone();
+            let cache_dir = self.cache_dir.clone();
+            let active_walks = self.active_walks.clone();
+            let stale_cache = self.stale_cache.clone();
+            active_walks.fetch_add(1, atomic::Ordering::AcqRel);
+            std::thread::spawn(move || {
+                let result = match cache_dir {
+                    Some(cache_dir) => injector
+                        .populate_files_cached(root.path, label, options, cancelled, cache_dir)
+                        .map(|stale| {
+                            if stale {
+                                stale_cache.store(true, atomic::Ordering::Release);
+                            }
+                        }),
+                    None => injector
+                        .populate_files_sorted(root.path, label, options, cancelled)
+                        .join()
+                        .unwrap_or_else(|_| {
+                            log::error!("The file searcher panicked");
+                            Ok(())
+                        }),
+                };
+                if let Err(err) = result {
+                    log::error!("{}", err);
+                }
+                active_walks.fetch_sub(1, atomic::Ordering::AcqRel);
+            });
+        }
+    }
+
     pub fn select(&mut self, index: u32) {
         self.selections.insert(index);
     }
@@ -390,14 +1175,37 @@ impl<T: Entry> Picker<T> {
         self.selections
             .par_iter()
             .filter_map(|selection| {
-                self.matcher
-                    .snapshot()
-                    .get_matched_item(*selection)
+                self.get_matched_item(*selection)
                     .map(|item| item.data.clone())
             })
             .collect()
     }
 
+    /// Up to `limit` of the matches or selections from `offset` on, as quickfix items.
+    /// Selections that no longer resolve to an item are left out before paging.
+    pub fn quickfix_items(
+        &self,
+        source: QuickfixSource,
+        offset: u32,
+        limit: u32,
+    ) -> Vec<QuickfixItem> {
+        match source {
+            QuickfixSource::Matches => self
+                .ordered_matches(offset..offset.saturating_add(limit))
+                .into_iter()
+                .map(|item| QuickfixItem::from_entry(item.data))
+                .collect(),
+            QuickfixSource::Selections => self
+                .selections
+                .iter()
+                .filter_map(|selection| self.get_matched_item(*selection))
+                .skip(offset as usize)
+                .take(limit as usize)
+                .map(|item| QuickfixItem::from_entry(item.data))
+                .collect(),
+        }
+    }
+
     pub fn cursor_pos(&self) -> Option<u32> {
         if self.total_matches() == 0 {
             None
@@ -444,7 +1252,39 @@ impl<T: Entry> BufferContents<T> for Picker<T> {
 #[derive(Clone, Debug, Deserialize, Serialize, Default)]
 pub struct Config {
     pub cwd: Option<String>,
+    /// Several directories to walk into the same picker, takes precedence over `cwd`
+    pub roots: Option<Vec<Root>>,
     pub sort_direction: Option<SortDirection>,
+    /// Whether the picker lists files or directories
+    pub source: Option<Source>,
+    pub git_ignore: Option<bool>,
+    /// How many directories deep to walk, `0` for unlimited
+    pub max_depth: Option<usize>,
+    /// Collect size, mtime and permissions for every entry while indexing
+    pub metadata: Option<bool>,
+    /// Leave archives and compressed files out of the walk
+    pub exclude_compressed: Option<bool>,
+    pub tiebreak: Option<Vec<Tiebreak>>,
+    /// The file the picker was opened from, used by the `proximity` tiebreak and boost
+    pub reference_path: Option<String>,
+    /// Score added to matches in the reference path's directory, divided by one more than
+    /// the number of directory hops for matches elsewhere
+    pub proximity_boost: Option<u32>,
+    /// Hide matches whose fuzzy score, before any proximity boost, is below this
+    pub min_score: Option<u32>,
+    /// How to order entries while the query is empty, walk order when unset
+    pub empty_query_order: Option<Vec<EmptyQueryOrder>>,
+    /// Paths of the files open in Neovim, used by the `open_buffers` order
+    pub open_buffers: Option<Vec<String>>,
+    /// Neovim's `v:oldfiles`, orders files not opened from the picker for `recently_opened`
+    pub oldfiles: Option<Vec<String>>,
+    /// Keep walked roots on disk so later pickers show results before the walk finishes
+    pub cache: Option<bool>,
+    /// Only read along with `cache`, defaults to `nucleo` inside Neovim's cache directory
+    pub cache_dir: Option<String>,
+    /// Where picker visits are saved for the `frecency` tiebreak and `recently_opened` order,
+    /// defaults to `nucleo/frecency` inside Neovim's data directory
+    pub frecency_path: Option<String>,
 }
 
 impl FromLua<'_> for Config {
@@ -452,7 +1292,40 @@ impl FromLua<'_> for Config {
         let table = LuaTable::from_lua(value, lua)?;
         Ok(Config {
             cwd: table.get("cwd")?,
+            roots: table.get("roots")?,
             sort_direction: table.get("sort_direction")?,
+            source: table.get("source")?,
+            git_ignore: table.get("git_ignore")?,
+            max_depth: table.get("max_depth")?,
+            metadata: table.get("metadata")?,
+            exclude_compressed: table.get("exclude_compressed")?,
+            tiebreak: table.get("tiebreak")?,
+            reference_path: table.get("reference_path")?,
+            proximity_boost: table.get("proximity_boost")?,
+            min_score: table.get("min_score")?,
+            empty_query_order: table.get("empty_query_order")?,
+            open_buffers: table.get("open_buffers")?,
+            oldfiles: table.get("oldfiles")?,
+            cache: table.get("cache")?,
+            cache_dir: match table.get::<_, Option<String>>("cache_dir")? {
+                Some(cache_dir) => Some(cache_dir),
+                None => Some(
+                    crate::path::stdpath(lua, "cache")
+                        .join("nucleo")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+            },
+            frecency_path: match table.get::<_, Option<String>>("frecency_path")? {
+                Some(frecency_path) => Some(frecency_path),
+                None => Some(
+                    crate::path::stdpath(lua, "data")
+                        .join("nucleo")
+                        .join("frecency")
+                        .to_string_lossy()
+                        .to_string(),
+                ),
+            },
         })
     }
 }
@@ -469,8 +1342,14 @@ impl<T: Entry> UserData for Picker<T> {
             Ok(())
         });
 
+        methods.add_method("roots", |lua, this, ()| lua.to_value(this.roots()));
+
         methods.add_method_mut("update_config", |_lua, this, params: (Config,)| {
-            this.update_config(params.0);
+            Ok(this.update_config(params.0))
+        });
+
+        methods.add_method_mut("record_open", |_lua, this, params: (String,)| {
+            this.record_open(params.0);
             Ok(())
         });
 
@@ -542,6 +1421,26 @@ impl<T: Entry> UserData for Picker<T> {
             Ok(lua.to_value(&this.current_matches()))
         });
 
+        methods.add_method(
+            "quickfix_items",
+            |lua, this, params: (Option<QuickfixSource>, Option<u32>, Option<u32>)| {
+                let items = this.quickfix_items(
+                    params.0.unwrap_or_default(),
+                    params.1.unwrap_or(0),
+                    params.2.unwrap_or(crate::quickfix::DEFAULT_CHUNK_SIZE),
+                );
+                lua.to_value(&items)
+            },
+        );
+
+        methods.add_method(
+            "matches",
+            |lua, this, params: (Option<u32>, Option<u32>)| {
+                let limit = params.1.unwrap_or_else(|| this.total_matches());
+                Ok(lua.to_value(&this.matches(params.0.unwrap_or(0), limit)))
+            },
+        );
+
         methods.add_method("total_items", |_lua, this, ()| Ok(this.total_items()));
         methods.add_method("total_matches", |_lua, this, ()| Ok(this.total_matches()));
 
@@ -550,13 +1449,10 @@ impl<T: Entry> UserData for Picker<T> {
         });
 
         methods.add_method("get_cursor_pos", |_lua, this, ()| Ok(this.cursor_pos()));
+        methods.add_method("lower_bound", |_lua, this, ()| Ok(this.lower_bound()));
 
         methods.add_method("get_selection", |lua, this, ()| {
-            match this
-                .matcher
-                .snapshot()
-                .get_matched_item(this.cursor.pos() as u32)
-            {
+            match this.get_matched_item(this.cursor.pos() as u32) {
                 Some(selection) => Ok(lua.to_value(selection.data)),
                 None => {
                     log::error!("Failed getting the selection at selection_index: {}, lower_bound: {}, upper_bound: {}", this.cursor.pos(), this.lower_bound(), this.upper_bound());
@@ -566,7 +1462,11 @@ impl<T: Entry> UserData for Picker<T> {
         });
 
         methods.add_method_mut("select", |_lua, this, params: (usize,)| {
-            this.select(params.0.try_into().expect("Selection index out of bounds"));
+            let index = params
+                .0
+                .try_into()
+                .map_err(|_| Error::SelectionOutOfBounds(params.0))?;
+            this.select(index);
             Ok(())
         });
 
@@ -600,3 +1500,345 @@ impl<T: Entry> UserData for Picker<T> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn settle(picker: &mut Picker<FileEntry>) {
+        let start = std::time::Instant::now();
+        while start.elapsed() < std::time::Duration::from_secs(10) {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            let status = picker.tick(10);
+            if !status.0.running
+                && picker.active_walks.load(atomic::Ordering::Acquire) == 0
+                && !picker.stale_cache.load(atomic::Ordering::Acquire)
+                && picker.pending_reload.is_none()
+            {
+                return;
+            }
+        }
+        panic!("The picker never settled");
+    }
+
+    fn file_name(entry: &FileEntry) -> String {
+        Path::new(entry.path())
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn match_names(picker: &Picker<FileEntry>) -> Vec<String> {
+        picker
+            .ordered_matches(0..picker.total_matches())
+            .iter()
+            .map(|item| file_name(item.data))
+            .collect()
+    }
+
+    const ROOT: &str = "/picker/root";
+
+    /// A picker whose matcher already contains `paths` under `ROOT`, matched against `query`
+    fn injected_picker<P: AsRef<str>>(
+        paths: impl IntoIterator<Item = P>,
+        query: &str,
+        config: Config,
+    ) -> Picker<FileEntry> {
+        let mut picker = Picker::new(ROOT.to_string(), SortDirection::Descending);
+        picker.update_config(config);
+        let injector = picker.matcher.injector();
+        for path in paths {
+            let path = Path::new(ROOT).join(path.as_ref());
+            let entry = FileEntry::from_path(&path, Some(ROOT.to_string())).unwrap();
+            injector.push(entry.clone(), |dst| dst[0] = entry.into_utf32());
+        }
+        picker.update_query(query.to_string());
+        while picker.tick(10).0.running {}
+        picker
+    }
+
+    fn match_paths(picker: &Picker<FileEntry>, range: Range<u32>) -> Vec<String> {
+        picker
+            .ordered_matches(range)
+            .iter()
+            .map(|item| item.data.match_value().to_string())
+            .collect()
+    }
+
+    fn reset_rank_cache(picker: &mut Picker<FileEntry>) {
+        *picker.rank_cache.get_mut() = RankCache::default();
+    }
+
+    /// The (start, end) positions of the ranked runs of tied matches
+    fn tie_runs(picker: &Picker<FileEntry>) -> Vec<(u32, u32)> {
+        picker
+            .rank_cache
+            .lock()
+            .ties
+            .iter()
+            .map(|(start, run)| (*start, run.end))
+            .collect()
+    }
+
+    fn cached_picker(root: &Path, cache_dir: &Path) -> Picker<FileEntry> {
+        let mut picker = Picker::new(String::new(), SortDirection::Descending);
+        picker.update_config(Config {
+            cwd: Some(root.to_string_lossy().to_string()),
+            cache: Some(true),
+            cache_dir: Some(cache_dir.to_string_lossy().to_string()),
+            ..Config::default()
+        });
+        picker
+    }
+
+    #[test]
+    fn reloads_deleted_entries_away_keeping_selections() {
+        let root = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        for file in ["a.rs", "b.rs", "zeta.rs"] {
+            fs::write(root.path().join(file), "").unwrap();
+        }
+        settle(&mut cached_picker(root.path(), cache_dir.path()));
+        fs::remove_file(root.path().join("a.rs")).unwrap();
+
+        let mut picker = cached_picker(root.path(), cache_dir.path());
+        while picker.active_walks.load(atomic::Ordering::Acquire) > 0 {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+        assert!(picker.stale_cache.load(atomic::Ordering::Acquire));
+
+        // Select from the cached items, which still include the deleted file
+        while picker.matcher.tick(10).0.running {}
+        assert_eq!(match_names(&picker), ["a.rs", "b.rs", "zeta.rs"]);
+        picker.select(2);
+
+        // The selection's item index differs from its match position once the query filters
+        picker.update_query("zeta".to_string());
+        settle(&mut picker);
+        assert_eq!(match_names(&picker), ["zeta.rs"]);
+        let selected = picker
+            .selections()
+            .iter()
+            .map(file_name)
+            .collect::<Vec<_>>();
+        assert_eq!(selected, ["zeta.rs"]);
+
+        picker.update_query(String::new());
+        settle(&mut picker);
+        assert_eq!(match_names(&picker), ["b.rs", "zeta.rs"]);
+    }
+
+    #[test]
+    fn drops_reselections_of_superseded_reloads() {
+        let root = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        for file in ["a.rs", "b.rs"] {
+            fs::write(root.path().join(file), "").unwrap();
+        }
+        let mut picker = cached_picker(root.path(), cache_dir.path());
+        settle(&mut picker);
+        picker.select(0);
+
+        picker.reload_from_cache();
+        picker.restart();
+        assert!(picker.pending_reload.is_none());
+        settle(&mut picker);
+        assert!(picker.selections().is_empty());
+    }
+
+    #[test]
+    fn length_tiebreak_reorders_ties() {
+        // nucleo breaks ties by the number of characters, the tiebreak by the number of bytes
+        let paths = ["日本語日本語/mod.rs", "abcdefghijklmn/mod.rs"];
+        let picker = injected_picker(paths, "mod", Config::default());
+        assert_eq!(match_paths(&picker, 0..2), paths);
+
+        let picker = injected_picker(
+            paths,
+            "mod",
+            Config {
+                tiebreak: Some(vec![Tiebreak::Length]),
+                ..Config::default()
+            },
+        );
+        assert_eq!(
+            match_paths(&picker, 0..2),
+            ["abcdefghijklmn/mod.rs", "日本語日本語/mod.rs"]
+        );
+    }
+
+    #[test]
+    fn frecency_tiebreak_reorders_ties() {
+        let paths = ["a/mod.rs", "bb/mod.rs", "ccc/mod.rs", "m/o/d.rs"];
+        let mut picker = injected_picker(
+            paths,
+            "mod",
+            Config {
+                tiebreak: Some(vec![Tiebreak::Frecency]),
+                ..Config::default()
+            },
+        );
+        assert_eq!(match_paths(&picker, 0..4), paths);
+
+        picker.record_open(format!("{}/ccc/mod.rs", ROOT));
+        // Only ties are reordered, the lower scored match stays last
+        assert_eq!(
+            match_paths(&picker, 0..4),
+            ["ccc/mod.rs", "a/mod.rs", "bb/mod.rs", "m/o/d.rs"]
+        );
+    }
+
+    #[test]
+    fn tiebreak_order_is_the_same_for_every_window() {
+        // A tie of files differing in length and frecency, followed by lower scored matches
+        let paths = (0..300)
+            .map(|n| format!("{}/mod.rs", "d".repeat(n % 7 + 1) + &n.to_string()))
+            .chain((0..50).map(|n| format!("m/o/d{}.rs", n)))
+            .collect::<Vec<_>>();
+        let mut picker = injected_picker(
+            &paths,
+            "mod",
+            Config {
+                tiebreak: Some(vec![Tiebreak::Frecency, Tiebreak::Length]),
+                ..Config::default()
+            },
+        );
+        for path in paths[..300].iter().step_by(37) {
+            picker.record_open(format!("{}/{}", ROOT, path));
+        }
+        let total = picker.total_matches();
+        assert_eq!(total, 350);
+        // The opened files lead, by length as they were opened equally often
+        let full = match_paths(&picker, 0..total);
+        let mut opened = paths[..300].iter().step_by(37).cloned().collect::<Vec<_>>();
+        opened.sort_by_key(|path| path.len());
+        assert_eq!(full[..opened.len()], opened);
+
+        // Each window is ranked from scratch, as well as after the windows before it
+        let windows = [250..270, 0..10, 295..305, 120..121, 340..350, 0..350];
+        for window in windows.clone() {
+            reset_rank_cache(&mut picker);
+            let expected = &full[window.start as usize..window.end as usize];
+            assert_eq!(match_paths(&picker, window), expected);
+        }
+        reset_rank_cache(&mut picker);
+        for window in windows {
+            let expected = &full[window.start as usize..window.end as usize];
+            assert_eq!(match_paths(&picker, window), expected);
+        }
+        // The tie is ranked as a whole, the lower scored matches on their own
+        assert_eq!(tie_runs(&picker), [(0, 300), (300, 350)]);
+    }
+
+    #[test]
+    fn tie_runs_are_widened_up_to_the_span() {
+        let count = 2 * MAX_TIE_SPAN + 5_000;
+        let picker = injected_picker(
+            (0..count).map(|n| format!("{}/mod.rs", n)),
+            "mod",
+            Config {
+                tiebreak: Some(vec![Tiebreak::Length]),
+                ..Config::default()
+            },
+        );
+        assert_eq!(picker.total_matches(), count);
+
+        let middle = MAX_TIE_SPAN + 2_000;
+        picker.ordered_matches(middle..middle + 10);
+        assert_eq!(
+            tie_runs(&picker),
+            [(middle - MAX_TIE_SPAN, middle + 1 + MAX_TIE_SPAN)]
+        );
+
+        // Later runs start where the earlier ones end rather than overlapping them
+        picker.ordered_matches(0..10);
+        picker.ordered_matches(count - 10..count);
+        assert_eq!(
+            tie_runs(&picker),
+            [
+                (0, middle - MAX_TIE_SPAN),
+                (middle - MAX_TIE_SPAN, middle + 1 + MAX_TIE_SPAN),
+                (middle + 1 + MAX_TIE_SPAN, count),
+            ]
+        );
+    }
+
+    #[test]
+    fn boosts_matches_near_the_reference_path() {
+        let paths = ["vendor/x/mod.rs", "src/picker/mod.rs"]
+            .into_iter()
+            .map(String::from)
+            .chain((0..40).map(|n| format!("lib{}/mod.rs", n)))
+            .chain((0..40).map(|n| format!("m/o/d{}.rs", n)))
+            .collect::<Vec<_>>();
+        let mut picker = injected_picker(
+            &paths,
+            "mod",
+            Config {
+                reference_path: Some(format!("{}/src/picker/view.rs", ROOT)),
+                proximity_boost: Some(40),
+                ..Config::default()
+            },
+        );
+        let total = picker.total_matches();
+        let names = |matches: Vec<FileEntry>| {
+            matches
+                .iter()
+                .map(|entry| entry.match_value().to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let full = names(picker.matches(0, total));
+        let position = |path: &str| full.iter().position(|name| name == path).unwrap();
+        assert_eq!(full[0], "src/picker/mod.rs");
+        assert!(position("src/picker/mod.rs") < position("vendor/x/mod.rs"));
+
+        // Overlapping windows agree, whether or not the windows before them were ranked
+        let windows = [(0, 5), (1, 3), (3, 20), (10, 40), (60, total), (0, total)];
+        for (offset, limit) in windows {
+            reset_rank_cache(&mut picker);
+            let expected = &full[offset as usize..(offset + limit).min(total) as usize];
+            assert_eq!(names(picker.matches(offset, limit)), expected);
+        }
+        reset_rank_cache(&mut picker);
+        for (offset, limit) in windows {
+            let expected = &full[offset as usize..(offset + limit).min(total) as usize];
+            assert_eq!(names(picker.matches(offset, limit)), expected);
+        }
+    }
+
+    #[test]
+    fn reports_the_score_that_min_score_filters_on() {
+        let config = || Config {
+            reference_path: Some(format!("{}/src/picker/view.rs", ROOT)),
+            proximity_boost: Some(40),
+            ..Config::default()
+        };
+        let paths = [
+            "vendor/x/mod.rs",
+            "src/picker/mod.rs",
+            "src/picker/m/o/d.rs",
+        ];
+        let mut picker = injected_picker(paths, "mod", config());
+        let matches = picker.matches(0, 3);
+        assert_eq!(matches[0].match_value, "src/picker/mod.rs");
+        assert_eq!(matches[0].score, matches[1].score);
+        assert!(matches[0].boost > matches[1].boost);
+
+        // The boost lifts the fuzzier match over the threshold without letting it through
+        let min_score = matches[0].score.unwrap();
+        assert!(matches[2].score.unwrap() < min_score);
+        assert!(matches[2].score.unwrap() + matches[2].boost.unwrap() >= min_score);
+        picker.update_config(Config {
+            min_score: Some(min_score),
+            ..config()
+        });
+        while picker.tick(10).0.running {}
+        let matches = picker.matches(0, 3);
+        assert_eq!(matches.len(), 2);
+        assert!(matches.iter().all(|entry| entry.score >= Some(min_score)));
+    }
+}
@@ -1,46 +1,696 @@
-use std::io::BufReader;
-use std::{collections::HashMap, fs::File};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
 
-use mlua::{UserData, UserDataMethods};
+use ignore::WalkBuilder;
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaString, LuaTable, LuaValue},
+    FromLua, LuaSerdeExt, UserData, UserDataMethods,
+};
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
 use ropey::Rope;
 use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::archive::ArchiveKind;
+use crate::bounded_cache::BoundedCache;
+use crate::encoding::{DecodedText, Encoding, LineEnding};
+use crate::format::format_size;
+use crate::git::{DiffLineKind, FileDiff};
+use crate::media::MediaKind;
+use crate::query::QueryMatchMode;
+use crate::syntax::HighlightSpan;
+
+/// A position to preview, such as a grep result, diagnostic or symbol
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreviewTarget {
+    /// Zero based line of the target
+    pub line: usize,
+    /// Zero based byte column of the target in the file, which may differ from the column in
+    /// the decoded text. Only the line is highlighted when unset.
+    pub col: Option<usize>,
+    /// Number of lines to return around the target
+    pub height: usize,
+    /// Text whose occurrences are highlighted, the word at the target when unset
+    pub text: Option<String>,
+}
+
+impl FromLua<'_> for PreviewTarget {
+    fn from_lua(value: LuaValue<'_>, lua: &'_ Lua) -> LuaResult<Self> {
+        let table = LuaTable::from_lua(value, lua)?;
+        Ok(PreviewTarget {
+            line: table.get("line")?,
+            col: table.get("col")?,
+            height: table.get("height")?,
+            text: table.get("text")?,
+        })
+    }
+}
+
+/// A byte range on one line of a preview
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatchRange {
+    pub line: usize,
+    pub start_col: usize,
+    pub end_col: usize,
+}
+
+/// The lines around a target, with the target's position relative to `contents`
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FocusedPreview {
+    pub contents: String,
+    /// Left out rather than serialized to a null, which Lua would treat as set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line_ending: Option<LineEnding>,
+    /// Only filled in for highlighted previews
+    pub highlights: Vec<HighlightSpan>,
+    /// Line of the file that `contents` starts at
+    pub start_line: usize,
+    pub target_line: usize,
+    pub target_col: usize,
+    pub matches: Vec<MatchRange>,
+}
+
+/// Byte ranges of every occurrence of `text` in `line`
+fn find_occurrences(line: &str, text: &str) -> Vec<(usize, usize)> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    line.match_indices(text)
+        .map(|(start, text)| (start, start + text.len()))
+        .collect()
+}
+
+/// Byte range of the word around `col`, or of the character at `col` outside of words
+fn word_at(line: &str, col: usize) -> Option<(usize, usize)> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let (start, c) = line
+        .char_indices()
+        .take_while(|(idx, _)| *idx <= col)
+        .last()?;
+    if !is_word(c) {
+        return Some((start, start + c.len_utf8()));
+    }
+
+    let word_start = line[..start]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(start, |(idx, _)| idx);
+    let word_end = line[start..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map_or(line.len(), |(idx, _)| start + idx);
+
+    Some((word_start, word_end))
+}
+
+/// How many levels below a previewed directory are listed
+const DIRECTORY_PREVIEW_DEPTH: usize = 3;
+/// Entries listed before a directory preview is cut off
+const DIRECTORY_PREVIEW_ENTRIES: usize = 500;
+
+struct TreeEntry {
+    depth: usize,
+    name: String,
+    size: Option<u64>,
+    last: bool,
+}
+
+/// A tree of the directory's contents, respecting gitignore and with file sizes
+fn preview_directory(path: &Path) -> String {
+    let mut entries = Vec::new();
+    let mut truncated = false;
+    let walker = WalkBuilder::new(path)
+        .hidden(false)
+        .git_ignore(true)
+        .max_depth(Some(DIRECTORY_PREVIEW_DEPTH))
+        .sort_by_file_name(std::cmp::Ord::cmp)
+        .filter_entry(|entry| entry.file_name() != ".git")
+        .build();
+    for entry in walker.flatten().filter(|entry| entry.depth() > 0) {
+        if entries.len() == DIRECTORY_PREVIEW_ENTRIES {
+            truncated = true;
+            break;
+        }
+        let is_dir = entry
+            .file_type()
+            .is_some_and(|file_type| file_type.is_dir());
+        let name = entry.file_name().to_string_lossy().to_string();
+        entries.push(TreeEntry {
+            depth: entry.depth(),
+            name: if is_dir { format!("{}/", name) } else { name },
+            size: (!is_dir)
+                .then(|| entry.metadata().ok().map(|metadata| metadata.len()))
+                .flatten(),
+            last: false,
+        });
+    }
+
+    // An entry is the last of its siblings when no entry at its depth follows before its parent ends
+    let mut sibling_follows = [false; DIRECTORY_PREVIEW_DEPTH + 2];
+    for entry in entries.iter_mut().rev() {
+        entry.last = !sibling_follows[entry.depth];
+        sibling_follows[entry.depth] = true;
+        sibling_follows[entry.depth + 1] = false;
+    }
+
+    let mut lines = vec![format!(
+        "{}/",
+        path.file_name().unwrap_or_default().to_string_lossy()
+    )];
+    let mut ancestors_last = Vec::new();
+    for entry in &entries {
+        ancestors_last.truncate(entry.depth - 1);
+        let mut line = ancestors_last
+            .iter()
+            .map(|last| if *last { "    " } else { "│   " })
+            .collect::<String>();
+        line.push_str(if entry.last {
+            "└── "
+        } else {
+            "├── "
+        });
+        line.push_str(&entry.name);
+        if let Some(size) = entry.size {
+            line.push_str("  ");
+            line.push_str(&format_size(size));
+        }
+        lines.push(line);
+        ancestors_last.push(entry.last);
+    }
+    if truncated {
+        lines.push("…".to_string());
+    }
+
+    lines.join("\n")
+}
+
+/// Huge files that can't be streamed, like UTF-16 ones, are only read up to the threshold
+fn read_file(path: &Path) -> Option<DecodedText> {
+    let bytes = match crate::line_index::is_huge(path) {
+        true => crate::line_index::read_head(path, crate::line_index::STREAM_THRESHOLD as usize),
+        false => fs::read(path),
+    };
+
+    crate::encoding::decode(bytes.ok()?)
+}
+
+/// Huge files are read a few lines at a time, unless they are UTF-16 where lines can't be
+/// found by looking for `\n` bytes
+fn is_streamable(path: &Path) -> bool {
+    crate::line_index::is_huge(path)
+        && crate::line_index::read_head(path, crate::encoding::SNIFF_LENGTH)
+            .is_ok_and(|head| !crate::encoding::is_utf16(&head))
+}
+
+/// Lines `start_line..end_line` of a huge file, without reading the rest of it
+fn read_file_lines(path: &Path, start_line: usize, end_line: usize) -> Option<DecodedText> {
+    let bytes = match crate::line_index::read_lines(path, start_line, end_line) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            log::warn!("Failed reading lines of {}: {}", path.display(), err);
+            return None;
+        }
+    };
+
+    crate::encoding::decode(bytes)
+}
+
+/// Previewed lines, with the encoding and line ending when they come from a text file
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Preview {
+    pub contents: String,
+    pub encoding: Option<Encoding>,
+    pub line_ending: Option<LineEnding>,
+    /// Only filled in for highlighted and diff previews
+    pub highlights: Vec<HighlightSpan>,
+}
+
+/// What a preview shows of a file
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum PreviewKind {
+    /// The contents, for Lua to highlight
+    #[default]
+    Plain,
+    /// The contents, highlighted with a bundled grammar
+    Highlighted,
+    /// The working tree changes against HEAD
+    Diff,
+}
+
+impl FromLua<'_> for PreviewKind {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                PreviewKind::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid preview kind: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Preview kinds must be strings")),
+        }
+    }
+}
+
+/// Loads the lines `start_line..end_line` of a file, a directory tree, an archive listing or a
+/// media summary
+fn load_preview(path: &Path, start_line: usize, end_line: usize) -> Preview {
+    log::info!("Previewing file {}", path.display());
+    let (text, encoding, line_ending) = if path.is_dir() {
+        (Rope::from(preview_directory(path)), None, None)
+    } else if let Some(listing) =
+        ArchiveKind::from_path(path).and_then(|kind| crate::archive::list_members(path, kind))
+    {
+        (Rope::from(listing), None, None)
+    } else if let Some(summary) =
+        MediaKind::from_path(path).and_then(|kind| crate::media::describe(path, kind))
+    {
+        (Rope::from(summary), None, None)
+    } else if is_streamable(path) {
+        return match read_file_lines(path, start_line, end_line) {
+            Some(decoded) => Preview {
+                contents: decoded.text,
+                encoding: Some(decoded.encoding),
+                line_ending: Some(decoded.line_ending),
+                highlights: Vec::new(),
+            },
+            None => Preview::default(),
+        };
+    } else {
+        match read_file(path) {
+            Some(decoded) => (
+                Rope::from(decoded.text),
+                Some(decoded.encoding),
+                Some(decoded.line_ending),
+            ),
+            None => return Preview::default(),
+        }
+    };
+    let end_line = text.len_lines().min(end_line);
+    let start_line = start_line.min(end_line);
+    let start_idx = text.line_to_char(start_line);
+    let end_idx = text.line_to_char(end_line);
+
+    Preview {
+        contents: text.slice(start_idx..end_idx).to_string(),
+        encoding,
+        line_ending,
+        highlights: Vec::new(),
+    }
+}
+
+/// Adds the highlights of a bundled grammar to a plain preview
+fn highlight_preview(path: &Path, preview: Preview) -> Preview {
+    let highlights = crate::syntax::highlight(path, &preview.contents);
+    Preview {
+        highlights,
+        ..preview
+    }
+}
+
+/// The working tree changes of the file against HEAD, with the diff lines highlighted
+fn load_diff(path: &Path, start_line: usize, end_line: usize) -> Preview {
+    let lines = match crate::git::diff_against_head(path) {
+        Ok(FileDiff::Lines(lines)) => lines,
+        Ok(FileDiff::Unchanged) => {
+            vec![(DiffLineKind::Context, "No changes against HEAD".to_string())]
+        }
+        Ok(FileDiff::Binary) => {
+            vec![(DiffLineKind::Context, "Binary file differs".to_string())]
+        }
+        Ok(FileDiff::TooLarge) => {
+            vec![(DiffLineKind::Context, "File too large to diff".to_string())]
+        }
+        Err(err) => vec![(DiffLineKind::Context, err.to_string())],
+    };
+
+    let lines = lines
+        .into_iter()
+        .skip(start_line)
+        .take(end_line.saturating_sub(start_line))
+        .collect::<Vec<_>>();
+    let highlights = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(line, (kind, text))| {
+            let hl_group = match kind {
+                DiffLineKind::Hunk => "DiffText",
+                DiffLineKind::Added => "DiffAdd",
+                DiffLineKind::Removed => "DiffDelete",
+                DiffLineKind::Context => return None,
+            };
+            Some(HighlightSpan {
+                line,
+                start_col: 0,
+                end_col: text.len(),
+                hl_group,
+            })
+        })
+        .collect();
+
+    Preview {
+        contents: lines
+            .into_iter()
+            .map(|(_, text)| text)
+            .collect::<Vec<_>>()
+            .join("\n"),
+        encoding: None,
+        line_ending: None,
+        highlights,
+    }
+}
+
+/// The `target.height` lines centered on `target`, shifted to stay within the file. Diffs aren't
+/// centered on targets, so they are loaded like plain previews.
+fn load_target(path: &Path, target: &PreviewTarget, kind: PreviewKind) -> FocusedPreview {
+    log::info!("Previewing {}:{}", path.display(), target.line);
+    // Huge files are only counted as far as the target needs, and read around it
+    let (text, mut encoding, mut line_ending) = match is_streamable(path) {
+        true => (None, None, None),
+        false => match read_file(path) {
+            Some(decoded) => (
+                Some(Rope::from(decoded.text)),
+                Some(decoded.encoding),
+                Some(decoded.line_ending),
+            ),
+            None => return FocusedPreview::default(),
+        },
+    };
+
+    let line_count = match &text {
+        // Ropey counts the empty line after a trailing newline
+        Some(text) => match text.len_lines() {
+            count if count > 1 && text.line(count - 1).len_chars() == 0 => count - 1,
+            count => count,
+        },
+        None => {
+            crate::line_index::count_lines_up_to(path, target.line.saturating_add(target.height))
+                .unwrap_or_default()
+        }
+    };
+    let line = target.line.min(line_count.saturating_sub(1));
+    let end_line = line
+        .saturating_sub(target.height / 2)
+        .saturating_add(target.height)
+        .min(line_count);
+    let start_line = end_line.saturating_sub(target.height);
+
+    let contents = match &text {
+        Some(text) => text
+            .slice(text.line_to_char(start_line)..text.line_to_char(end_line))
+            .to_string(),
+        None => match read_file_lines(path, start_line, end_line) {
+            Some(decoded) => {
+                encoding = Some(decoded.encoding);
+                line_ending = Some(decoded.line_ending);
+                decoded.text
+            }
+            None => String::new(),
+        },
+    };
+    let target_line = line - start_line;
+
+    // The column counts bytes of the file, which transcoding may have changed
+    let target_col = match (target.col, encoding) {
+        (Some(col), Some(encoding)) => {
+            let bom_len = match line {
+                0 => crate::line_index::read_head(path, 3)
+                    .map(|head| crate::encoding::bom_len(&head))
+                    .unwrap_or_default(),
+                _ => 0,
+            };
+            let target_text = contents.lines().nth(target_line).unwrap_or_default();
+            Some(crate::encoding::decoded_col(
+                target_text,
+                col,
+                encoding,
+                bom_len,
+            ))
+        }
+        (col, _) => col,
+    };
+
+    let matches = contents
+        .lines()
+        .enumerate()
+        .flat_map(|(line_nr, line)| {
+            let ranges = match &target.text {
+                Some(text) => find_occurrences(line, text),
+                None if line_nr == target_line => target_col
+                    .and_then(|col| word_at(line, col))
+                    .into_iter()
+                    .collect(),
+                None => Vec::new(),
+            };
+            ranges
+                .into_iter()
+                .map(move |(start_col, end_col)| MatchRange {
+                    line: line_nr,
+                    start_col,
+                    end_col,
+                })
+        })
+        .collect();
+
+    let highlights = match kind {
+        PreviewKind::Highlighted => crate::syntax::highlight(path, &contents),
+        PreviewKind::Plain | PreviewKind::Diff => Vec::new(),
+    };
+
+    FocusedPreview {
+        contents,
+        encoding,
+        line_ending,
+        highlights,
+        start_line,
+        target_line,
+        target_col: target_col.unwrap_or_default(),
+        matches,
+    }
+}
+
+/// Loads previews off the main thread. Separate from the global pool so that slow reads never
+/// hold up the picker's parallel sorts, which the main thread waits on.
+static PREVIEW_POOL: Lazy<Option<rayon::ThreadPool>> = Lazy::new(|| {
+    match rayon::ThreadPoolBuilder::new()
+        .thread_name(|idx| format!("nucleo-preview-{}", idx))
+        .panic_handler(|_| log::error!("A preview panicked"))
+        .build()
+    {
+        Ok(pool) => Some(pool),
+        Err(err) => {
+            log::error!("Failed to start the preview pool: {}", err);
+            None
+        }
+    }
+});
+
+/// Runs `load` on the preview pool, or on its own thread when the pool failed to start
+fn spawn_load(load: impl FnOnce() + Send + 'static) {
+    match PREVIEW_POOL.as_ref() {
+        Some(pool) => pool.spawn(load),
+        None => {
+            std::thread::spawn(load);
+        }
+    }
+}
+
+/// Previews kept around before the oldest are dropped
+const MAX_CACHED_PREVIEWS: usize = 256;
+
+type PreviewKey = (PathBuf, usize, usize, PreviewKind);
+
+/// A preview that is filled in once its read on the background pool finishes
+pub type PreviewSlot = Arc<OnceCell<Preview>>;
+
+type PreviewCache = BoundedCache<PreviewKey, PreviewSlot, MAX_CACHED_PREVIEWS>;
+
+/// Handed to Lua while a preview loads in the background
+#[derive(Debug, Clone)]
+pub struct PendingPreview(PreviewSlot);
+
+impl UserData for PendingPreview {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("ready", |_lua, this, ()| Ok(this.0.get().is_some()));
+        methods.add_method("contents", |_lua, this, ()| {
+            Ok(this.0.get().map(|preview| preview.contents.clone()))
+        });
+        methods.add_method("encoding", |_lua, this, ()| {
+            Ok(this
+                .0
+                .get()
+                .and_then(|preview| preview.encoding)
+                .map(|encoding| encoding.to_string()))
+        });
+        methods.add_method("line_ending", |_lua, this, ()| {
+            Ok(this
+                .0
+                .get()
+                .and_then(|preview| preview.line_ending)
+                .map(|line_ending| line_ending.to_string()))
+        });
+        methods.add_method("highlights", |lua, this, ()| {
+            lua.to_value(&this.0.get().map(|preview| &preview.highlights))
+        });
+    }
+}
+
+/// Handed to Lua while the lines around a target load in the background
+#[derive(Debug, Clone, Default)]
+pub struct PendingTarget(Arc<OnceCell<FocusedPreview>>);
+
+impl UserData for PendingTarget {
+    fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
+        methods.add_method("ready", |_lua, this, ()| Ok(this.0.get().is_some()));
+        methods.add_method("preview", |lua, this, ()| lua.to_value(&this.0.get()));
+    }
+}
 
-// TODO: Add caching
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Previewer {
     #[serde(skip)]
-    file_cache: HashMap<String, String>,
+    file_cache: Arc<Mutex<PreviewCache>>,
 }
 
 impl Previewer {
     pub fn new() -> Self {
         Self {
-            file_cache: HashMap::new(),
+            file_cache: Arc::default(),
         }
     }
 
-    pub fn preview_file(&mut self, path: &str, start_line: usize, end_line: usize) -> String {
-        log::info!("Previewing file {}", path);
-        if let Some(contents) = self.file_cache.get(path) {
-            log::info!("Using cached contents for {}", path);
-            return contents.to_string();
-        };
-        let file = match File::open(path) {
-            Ok(file) => file,
-            Err(_) => return String::new(),
-        };
-        let text = match Rope::from_reader(BufReader::new(file)) {
-            Ok(rope) => rope,
-            Err(_) => return String::new(),
+    /// Starts loading the preview on the background pool unless it is cached or loading already
+    fn request(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+        kind: PreviewKind,
+    ) -> PreviewSlot {
+        let mut cache = self.file_cache.lock();
+        let (slot, created) = cache.get_or_insert_with(
+            (path.to_path_buf(), start_line, end_line, kind),
+            PreviewSlot::default,
+        );
+        if !created {
+            return slot;
+        }
+
+        // Highlighting starts from the plain preview, which query matches are found in as well
+        let plain = (kind == PreviewKind::Highlighted).then(|| {
+            cache
+                .get_or_insert_with(
+                    (path.to_path_buf(), start_line, end_line, PreviewKind::Plain),
+                    PreviewSlot::default,
+                )
+                .0
+        });
+        drop(cache);
+
+        let path = path.to_path_buf();
+        let pending = slot.clone();
+        let load = move || {
+            // The slot has to be filled even when loading panics, or Lua would wait on it forever
+            let preview = std::panic::catch_unwind(|| match kind {
+                PreviewKind::Plain => load_preview(&path, start_line, end_line),
+                PreviewKind::Highlighted => {
+                    let plain = plain.map_or_else(
+                        || load_preview(&path, start_line, end_line),
+                        |plain| {
+                            plain
+                                .get_or_init(|| load_preview(&path, start_line, end_line))
+                                .clone()
+                        },
+                    );
+                    highlight_preview(&path, plain)
+                }
+                PreviewKind::Diff => load_diff(&path, start_line, end_line),
+            })
+            .unwrap_or_else(|_| {
+                log::error!("Previewing {} panicked", path.display());
+                Preview::default()
+            });
+            pending.get_or_init(|| preview);
         };
-        let end_line = text.len_lines().min(end_line);
-        let start_idx = text.line_to_char(start_line);
-        let end_idx = text.line_to_char(end_line);
+        spawn_load(load);
+
+        slot
+    }
+
+    /// Blocks until the preview is loaded, sharing the work with a prefetch of the same range
+    pub fn preview_file(&mut self, path: &Path, start_line: usize, end_line: usize) -> String {
+        self.preview(path, start_line, end_line).contents
+    }
 
-        let content = text.slice(start_idx..end_idx).to_string();
-        self.file_cache.insert(path.to_string(), content.clone());
+    /// Like `preview_file`, along with the detected encoding and line ending
+    pub fn preview(&mut self, path: &Path, start_line: usize, end_line: usize) -> Preview {
+        let (slot, _) = self.file_cache.lock().get_or_insert_with(
+            (path.to_path_buf(), start_line, end_line, PreviewKind::Plain),
+            PreviewSlot::default,
+        );
 
-        content
+        slot.get_or_init(|| load_preview(path, start_line, end_line))
+            .clone()
+    }
+
+    /// Returns right away with a handle that is ready once the preview has loaded
+    pub fn preview_async(
+        &self,
+        path: &Path,
+        start_line: usize,
+        end_line: usize,
+        kind: PreviewKind,
+    ) -> PendingPreview {
+        PendingPreview(self.request(path, start_line, end_line, kind))
+    }
+
+    /// Loads the previews of `paths` in the background so that moving to them is instant
+    pub fn prefetch(
+        &self,
+        paths: &[PathBuf],
+        start_line: usize,
+        end_line: usize,
+        kind: PreviewKind,
+    ) {
+        for path in paths {
+            self.request(path, start_line, end_line, kind);
+        }
+    }
+
+    /// Returns right away with a handle that is ready once the lines around `target` have loaded
+    pub fn preview_target(
+        &self,
+        path: &Path,
+        target: &PreviewTarget,
+        kind: PreviewKind,
+    ) -> PendingTarget {
+        let pending = PendingTarget::default();
+        let slot = pending.0.clone();
+        let path = path.to_path_buf();
+        let target = target.clone();
+        spawn_load(move || {
+            let preview = std::panic::catch_unwind(|| load_target(&path, &target, kind))
+                .unwrap_or_else(|_| {
+                    log::error!("Previewing {}:{} panicked", path.display(), target.line);
+                    FocusedPreview::default()
+                });
+            slot.get_or_init(|| preview);
+        });
+
+        pending
+    }
+}
+
+impl Default for Previewer {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -48,14 +698,65 @@ impl UserData for Previewer {
     fn add_methods<'lua, M: UserDataMethods<'lua, Self>>(methods: &mut M) {
         methods.add_method_mut(
             "preview_file",
-            |_lua, this, params: (Option<String>, usize, usize)| match params.0 {
-                Some(path) => Ok(this.preview_file(&path, params.1, params.2)),
+            |_lua, this, params: (Option<LuaString>, usize, usize)| match params.0 {
+                Some(path) => Ok(this.preview_file(
+                    &crate::path::from_bytes(path.as_bytes()),
+                    params.1,
+                    params.2,
+                )),
                 None => Ok(String::new()),
             },
         );
 
+        methods.add_method(
+            "preview_async",
+            |_lua, this, params: (LuaString, usize, usize, PreviewKind)| {
+                Ok(this.preview_async(
+                    &crate::path::from_bytes(params.0.as_bytes()),
+                    params.1,
+                    params.2,
+                    params.3,
+                ))
+            },
+        );
+
+        methods.add_method(
+            "prefetch",
+            |_lua, this, params: (Vec<LuaString>, usize, usize, PreviewKind)| {
+                let paths = params
+                    .0
+                    .iter()
+                    .map(|path| crate::path::from_bytes(path.as_bytes()))
+                    .collect::<Vec<_>>();
+                this.prefetch(&paths, params.1, params.2, params.3);
+                Ok(())
+            },
+        );
+
+        methods.add_method(
+            "query_matches",
+            |lua, _this, params: (String, String, Option<QueryMatchMode>)| {
+                lua.to_value(&crate::query::query_ranges(
+                    &params.0,
+                    &params.1,
+                    params.2.unwrap_or_default(),
+                ))
+            },
+        );
+
+        methods.add_method(
+            "preview_target",
+            |_lua, this, params: (LuaString, PreviewTarget, PreviewKind)| {
+                Ok(this.preview_target(
+                    &crate::path::from_bytes(params.0.as_bytes()),
+                    &params.1,
+                    params.2,
+                ))
+            },
+        );
+
         methods.add_method_mut("reset", |_lua, this, ()| {
-            this.file_cache.clear();
+            this.file_cache.lock().clear();
             Ok(())
         });
     }
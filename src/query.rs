@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaValue},
+    FromLua, IntoLua,
+};
+use nucleo::pattern::{CaseMatching, Pattern};
+use nucleo::Utf32Str;
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::picker::STRING_MATCHER;
+use crate::previewer::MatchRange;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum QueryMatchMode {
+    /// Every occurrence of each query word
+    #[default]
+    Literal,
+    /// The characters of lines that fuzzy match the whole query, as in the picker
+    Fuzzy,
+}
+
+impl FromLua<'_> for QueryMatchMode {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                QueryMatchMode::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid query match mode: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Query match modes must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for QueryMatchMode {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+/// Merges sorted character indices into byte ranges of `line`
+fn byte_ranges(line: &str, indices: &[u32]) -> Vec<(usize, usize)> {
+    let offsets = line
+        .char_indices()
+        .map(|(offset, c)| (offset, offset + c.len_utf8()))
+        .collect::<Vec<_>>();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (start, end) in indices.iter().filter_map(|idx| offsets.get(*idx as usize)) {
+        match ranges.last_mut() {
+            Some(last) if last.1 == *start => last.1 = *end,
+            _ => ranges.push((*start, *end)),
+        }
+    }
+
+    ranges
+}
+
+/// Byte ranges of every occurrence of `word` in `line`, ignoring case unless `word` has capitals
+fn literal_ranges(line: &str, word: &str) -> Vec<(usize, usize)> {
+    let ignore_case = !word.chars().any(char::is_uppercase);
+    let fold = |c: char| match ignore_case {
+        true => c.to_lowercase().next().unwrap_or(c),
+        false => c,
+    };
+    let needle = word.chars().map(fold).collect::<Vec<_>>();
+    let haystack = line.chars().map(fold).collect::<Vec<_>>();
+
+    let mut indices = Vec::new();
+    let mut start = 0;
+    while start + needle.len() <= haystack.len() {
+        if haystack[start..start + needle.len()] == needle[..] {
+            indices.extend(start as u32..(start + needle.len()) as u32);
+            start += needle.len();
+        } else {
+            start += 1;
+        }
+    }
+
+    byte_ranges(line, &indices)
+}
+
+/// Where `query` matches inside the lines of `contents`
+pub fn query_ranges(contents: &str, query: &str, mode: QueryMatchMode) -> Vec<MatchRange> {
+    if query.trim().is_empty() {
+        return Vec::new();
+    }
+
+    let pattern = Pattern::parse(query, CaseMatching::Smart);
+    let string_matcher = &mut STRING_MATCHER.lock().0;
+    let mut buf = Vec::new();
+    let mut indices = Vec::new();
+
+    contents
+        .lines()
+        .enumerate()
+        .flat_map(|(line_nr, line)| {
+            let ranges = match mode {
+                QueryMatchMode::Literal => {
+                    let mut ranges = query
+                        .split_whitespace()
+                        .flat_map(|word| literal_ranges(line, word))
+                        .collect::<Vec<_>>();
+                    ranges.sort_unstable();
+                    ranges
+                }
+                QueryMatchMode::Fuzzy => {
+                    indices.clear();
+                    let haystack = Utf32Str::new(line, &mut buf);
+                    match pattern.indices(haystack, string_matcher, &mut indices) {
+                        Some(_) => {
+                            indices.sort_unstable();
+                            indices.dedup();
+                            byte_ranges(line, &indices)
+                        }
+                        None => Vec::new(),
+                    }
+                }
+            };
+
+            ranges
+                .into_iter()
+                .map(move |(start_col, end_col)| MatchRange {
+                    line: line_nr,
+                    start_col,
+                    end_col,
+                })
+        })
+        .collect()
+}
@@ -0,0 +1,63 @@
+use std::str::FromStr;
+
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaValue},
+    FromLua, IntoLua,
+};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::picker::Entry;
+
+/// Items handed to Lua per call, large sets are fetched a chunk at a time
+pub const DEFAULT_CHUNK_SIZE: u32 = 1000;
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum QuickfixSource {
+    /// Every match in display order
+    #[default]
+    Matches,
+    /// The selected matches in the order they appear
+    Selections,
+}
+
+impl FromLua<'_> for QuickfixSource {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                QuickfixSource::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid quickfix source: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Quickfix sources must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for QuickfixSource {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+/// An entry in the shape `setqflist()` and `setloclist()` expect
+#[derive(Debug, Clone, Serialize)]
+pub struct QuickfixItem {
+    #[serde(with = "serde_bytes")]
+    pub filename: Vec<u8>,
+    pub lnum: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+impl QuickfixItem {
+    pub fn from_entry<T: Entry>(entry: &T) -> Self {
+        Self {
+            filename: entry.raw_path().to_vec(),
+            lnum: 1,
+            col: 1,
+            text: entry.match_value().to_string(),
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxReference, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+/// Lines longer than this are usually minified or generated, highlighting stops at the first one
+const MAX_HIGHLIGHT_LINE_LENGTH: usize = 1000;
+
+static SYNTAX_SET: Lazy<SyntaxSet> = Lazy::new(SyntaxSet::load_defaults_newlines);
+
+/// Scope prefixes and the Neovim highlight group they map to, more specific prefixes first
+const SCOPE_GROUPS: &[(&str, &str)] = &[
+    ("comment", "@comment"),
+    ("string.regexp", "@string.regexp"),
+    ("string", "@string"),
+    ("constant.character.escape", "@string.escape"),
+    ("constant.numeric", "@number"),
+    ("constant.language", "@constant.builtin"),
+    ("constant", "@constant"),
+    ("keyword.operator", "@operator"),
+    ("keyword", "@keyword"),
+    ("storage.type", "@keyword.type"),
+    ("storage", "@keyword.modifier"),
+    ("entity.name.function", "@function"),
+    ("entity.name.tag", "@tag"),
+    ("entity.name.namespace", "@module"),
+    ("entity.name.section", "@markup.heading"),
+    ("entity.name", "@type"),
+    ("entity.other.attribute-name", "@tag.attribute"),
+    ("entity.other.inherited-class", "@type"),
+    ("support.function", "@function.builtin"),
+    ("support.type", "@type.builtin"),
+    ("support.class", "@type.builtin"),
+    ("support.constant", "@constant.builtin"),
+    ("variable.parameter", "@variable.parameter"),
+    ("variable.language", "@variable.builtin"),
+    ("variable.function", "@function.call"),
+    ("variable.other.member", "@variable.member"),
+    ("variable", "@variable"),
+    ("markup.heading", "@markup.heading"),
+    ("markup.bold", "@markup.strong"),
+    ("markup.italic", "@markup.italic"),
+    ("markup.underline.link", "@markup.link.url"),
+    ("markup.raw", "@markup.raw"),
+    ("markup.quote", "@markup.quote"),
+    ("markup.list", "@markup.list"),
+    ("punctuation.definition", ""),
+    ("punctuation.section", "@punctuation.bracket"),
+    ("punctuation", "@punctuation.delimiter"),
+];
+
+/// A highlight group applied to a byte range of one line of the previewed text
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct HighlightSpan {
+    pub line: usize,
+    pub start_col: usize,
+    pub end_col: usize,
+    pub hl_group: &'static str,
+}
+
+/// The highlight group of the innermost scope that has one.
+/// `punctuation.definition` scopes defer to their parent, so a comment's `//` is a comment.
+fn scope_group(stack: &ScopeStack) -> Option<&'static str> {
+    stack.as_slice().iter().rev().find_map(|scope: &Scope| {
+        let name = scope.build_string();
+        SCOPE_GROUPS
+            .iter()
+            .find(|(prefix, _)| {
+                name == *prefix
+                    || name
+                        .strip_prefix(prefix)
+                        .is_some_and(|rest| rest.starts_with('.'))
+            })
+            .map(|(_, group)| *group)
+            .filter(|group| !group.is_empty())
+    })
+}
+
+/// Adds a span for the scopes on `stack`, extending the previous span when it has the same group
+fn push_span(
+    spans: &mut Vec<HighlightSpan>,
+    line: usize,
+    start_col: usize,
+    end_col: usize,
+    stack: &ScopeStack,
+) {
+    let hl_group = match scope_group(stack) {
+        Some(hl_group) if end_col > start_col => hl_group,
+        _ => return,
+    };
+    match spans.last_mut() {
+        Some(last)
+            if last.line == line && last.end_col == start_col && last.hl_group == hl_group =>
+        {
+            last.end_col = end_col;
+        }
+        _ => spans.push(HighlightSpan {
+            line,
+            start_col,
+            end_col,
+            hl_group,
+        }),
+    }
+}
+
+fn find_syntax(path: &Path, text: &str) -> Option<&'static SyntaxReference> {
+    let by_extension = path
+        .extension()
+        .and_then(|ext| SYNTAX_SET.find_syntax_by_extension(&ext.to_string_lossy()));
+    let by_name = || {
+        path.file_name()
+            .and_then(|name| SYNTAX_SET.find_syntax_by_extension(&name.to_string_lossy()))
+    };
+    let by_first_line = || {
+        text.lines()
+            .next()
+            .and_then(|line| SYNTAX_SET.find_syntax_by_first_line(line))
+    };
+
+    by_extension.or_else(by_name).or_else(by_first_line)
+}
+
+/// Tokenizes `text` with the grammar matching `path`. Parsing starts at the first line of
+/// `text`, so constructs opened above a preview that starts mid-file aren't recognized.
+pub fn highlight(path: &Path, text: &str) -> Vec<HighlightSpan> {
+    let syntax = match find_syntax(path, text) {
+        Some(syntax) => syntax,
+        None => return Vec::new(),
+    };
+
+    let mut state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+    let mut spans: Vec<HighlightSpan> = Vec::new();
+    for (line_nr, line) in LinesWithEndings::from(text).enumerate() {
+        if line.len() > MAX_HIGHLIGHT_LINE_LENGTH {
+            break;
+        }
+        let ops = match state.parse_line(line, &SYNTAX_SET) {
+            Ok(ops) => ops,
+            Err(err) => {
+                log::warn!("Failed highlighting {}: {}", path.display(), err);
+                break;
+            }
+        };
+
+        let line_len = line.trim_end_matches(['\n', '\r']).len();
+        let mut col = 0;
+        for (offset, op) in ops {
+            let offset = offset.min(line_len);
+            push_span(&mut spans, line_nr, col, offset, &stack);
+            col = offset;
+            if stack.apply(&op).is_err() {
+                return spans;
+            }
+        }
+        push_span(&mut spans, line_nr, col, line_len, &stack);
+    }
+
+    spans
+}
@@ -0,0 +1,84 @@
+use std::cmp::Ordering;
+use std::path::Path;
+use std::str::FromStr;
+
+use mlua::{
+    prelude::{Lua, LuaError, LuaResult, LuaValue},
+    FromLua, IntoLua,
+};
+use serde::{Deserialize, Serialize};
+use strum::{Display, EnumString};
+
+use crate::frecency::Frecency;
+use crate::picker::Entry;
+
+/// Orders matches that nucleo scored equally, applied in the configured order
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, EnumString, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Tiebreak {
+    /// Shorter paths first
+    Length,
+    /// Paths closer to the reference path's directory first
+    Proximity,
+    /// More recently modified files first, requires `metadata = true`
+    Mtime,
+    /// More frequently and recently opened files first
+    Frecency,
+}
+
+impl FromLua<'_> for Tiebreak {
+    fn from_lua(value: LuaValue<'_>, _lua: &'_ Lua) -> LuaResult<Self> {
+        match value {
+            mlua::Value::String(str) => {
+                let str = str.to_str()?;
+                Tiebreak::from_str(str)
+                    .map_err(|_| LuaError::runtime(format!("Invalid tiebreak: {}", str)))
+            }
+            _ => Err(LuaError::runtime("Tiebreaks must be strings")),
+        }
+    }
+}
+
+impl IntoLua<'_> for Tiebreak {
+    fn into_lua(self, lua: &'_ Lua) -> LuaResult<LuaValue<'_>> {
+        self.to_string().into_lua(lua)
+    }
+}
+
+pub struct TiebreakContext<'a> {
+    pub reference_dir: Option<&'a Path>,
+    pub frecency: &'a Frecency,
+    pub now: u64,
+}
+
+impl Tiebreak {
+    pub fn compare<T: Entry>(&self, a: &T, b: &T, context: &TiebreakContext) -> Ordering {
+        match self {
+            Tiebreak::Length => a.match_value().len().cmp(&b.match_value().len()),
+            Tiebreak::Proximity => match context.reference_dir {
+                Some(dir) => crate::path::directory_distance(dir, Path::new(a.path()))
+                    .cmp(&crate::path::directory_distance(dir, Path::new(b.path()))),
+                None => Ordering::Equal,
+            },
+            Tiebreak::Mtime => b.mtime().cmp(&a.mtime()),
+            Tiebreak::Frecency => context
+                .frecency
+                .score(b.path(), context.now)
+                .cmp(&context.frecency.score(a.path(), context.now)),
+        }
+    }
+}
+
+/// Compares with each tiebreak in turn until one of them tells the entries apart
+pub fn compare_all<T: Entry>(
+    tiebreaks: &[Tiebreak],
+    a: &T,
+    b: &T,
+    context: &TiebreakContext,
+) -> Ordering {
+    tiebreaks
+        .iter()
+        .map(|tiebreak| tiebreak.compare(a, b, context))
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(Ordering::Equal)
+}